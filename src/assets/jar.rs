@@ -1,15 +1,14 @@
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
 
 use bevy::asset::{AssetIo, AssetIoError, BoxedFuture};
+use vakfu_format::jar::Jar;
 
 use super::{AssetIoProps, CustomAssetIoPlugin};
 
 pub struct JarAssetIo {
     base: Box<dyn AssetIo>,
-    archive: Mutex<zip::ZipArchive<BufReader<File>>>,
+    jar: Jar,
 }
 
 impl JarAssetIo {
@@ -22,10 +21,9 @@ impl<P: AsRef<Path>> TryFrom<AssetIoProps<P>> for JarAssetIo {
     type Error = anyhow::Error;
 
     fn try_from(props: AssetIoProps<P>) -> Result<Self, Self::Error> {
-        let archive = zip::ZipArchive::new(BufReader::new(File::open(props.props)?))?;
         let io = JarAssetIo {
             base: props.base,
-            archive: Mutex::new(archive),
+            jar: Jar::open(props.props)?,
         };
         Ok(io)
     }
@@ -34,13 +32,12 @@ impl<P: AsRef<Path>> TryFrom<AssetIoProps<P>> for JarAssetIo {
 impl AssetIo for JarAssetIo {
     fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
         Box::pin(async {
-            let mut archive = self.archive.lock().unwrap();
-            let mut entry = archive
-                .by_name(&path.to_string_lossy())
-                .map_err(|_| AssetIoError::NotFound(path.to_owned()))?;
-            let mut bytes = Vec::with_capacity(entry.size() as usize);
-            entry.read_to_end(&mut bytes)?;
-            Ok(bytes)
+            self.jar
+                .read(&path.to_string_lossy())
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::NotFound => AssetIoError::NotFound(path.to_owned()),
+                    _ => AssetIoError::Io(err),
+                })
         })
     }
 
@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use pico_args::Arguments;
+use serde_json::{json, Value};
+use vakfu_format::diff::{position, LibraryDiff, MapDiff};
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::sprite::MapSprite;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let old = GamePaths::new(pargs.value_from_str::<_, PathBuf>("--old")?);
+    let new = GamePaths::new(pargs.value_from_str::<_, PathBuf>("--new")?);
+    let map: Option<i32> = pargs.opt_value_from_str("--map")?;
+    let json = pargs.contains("--json");
+
+    let old_lib = ElementLibrary::load(File::open(old.library())?)?;
+    let new_lib = ElementLibrary::load(File::open(new.library())?)?;
+    let library = LibraryDiff::new(&old_lib, &new_lib);
+
+    // every map is compared unless one is given, in which case its sprites are listed as well
+    let ids: BTreeSet<i32> = match map {
+        Some(id) => [id].into(),
+        None => old.map_ids()?.into_iter().chain(new.map_ids()?).collect(),
+    };
+    let mut report = Report {
+        library: &library,
+        added_maps: vec![],
+        removed_maps: vec![],
+        maps: vec![],
+        details: map.is_some(),
+    };
+    for id in ids {
+        let (old_path, new_path) = (old.map(id), new.map(id));
+        match (old_path.exists(), new_path.exists()) {
+            (false, true) => report.added_maps.push(id),
+            (true, false) => report.removed_maps.push(id),
+            (true, true) => {
+                let (old_map, new_map) = match (load_map(&old_path), load_map(&new_path)) {
+                    (Ok(old_map), Ok(new_map)) => (old_map, new_map),
+                    (Err(err), _) | (_, Err(err)) => {
+                        eprintln!("failed to load map {}: {:#}", id, err);
+                        continue;
+                    }
+                };
+                let diff = MapDiff::new(&old_map, &old_lib, &new_map, &new_lib);
+                if !diff.is_empty() || report.details {
+                    report
+                        .maps
+                        .push((id, summary(&diff), report.details.then(|| details(&diff))));
+                }
+            }
+            (false, false) => eprintln!("map {} is in neither installation", id),
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+    } else {
+        report.print();
+    }
+    Ok(())
+}
+
+struct Report<'a> {
+    library: &'a LibraryDiff,
+    added_maps: Vec<i32>,
+    removed_maps: Vec<i32>,
+    // maps in both installations that differ, with their changes listed when asked for
+    maps: Vec<(i32, Value, Option<Value>)>,
+    details: bool,
+}
+
+impl<'a> Report<'a> {
+    fn to_json(&self) -> Value {
+        let changed = self
+            .library
+            .changed
+            .iter()
+            .map(|(id, fields)| json!({ "element": id, "fields": fields }))
+            .collect::<Vec<_>>();
+        let maps = self
+            .maps
+            .iter()
+            .map(|(id, summary, details)| {
+                let mut map = json!({ "map": id, "summary": summary });
+                if let Some(details) = details {
+                    map["sprites"] = details.clone();
+                }
+                map
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "elements": {
+                "added": self.library.added,
+                "removed": self.library.removed,
+                "changed": changed,
+            },
+            "added_maps": self.added_maps,
+            "removed_maps": self.removed_maps,
+            "maps": maps,
+        })
+    }
+
+    fn print(&self) {
+        let library = self.library;
+        println!(
+            "elements: {} added, {} removed, {} changed",
+            library.added.len(),
+            library.removed.len(),
+            library.changed.len()
+        );
+        print_ids("added", &library.added);
+        print_ids("removed", &library.removed);
+        for (id, fields) in &library.changed {
+            println!("  changed {}: {}", id, fields.join(", "));
+        }
+
+        if !self.added_maps.is_empty() || !self.removed_maps.is_empty() {
+            println!(
+                "maps: {} added, {} removed",
+                self.added_maps.len(),
+                self.removed_maps.len()
+            );
+            print_ids("added", &self.added_maps);
+            print_ids("removed", &self.removed_maps);
+        }
+
+        for (id, summary, details) in &self.maps {
+            println!(
+                "map {}: {} added, {} removed, {} moved, {} tints changed, {} textures changed, {} unchanged",
+                id,
+                summary["added"],
+                summary["removed"],
+                summary["moved"],
+                summary["tint_changed"],
+                summary["texture_changed"],
+                summary["unchanged"]
+            );
+            let details = match details {
+                Some(details) => details,
+                None => continue,
+            };
+            for sprite in details["added"].as_array().into_iter().flatten() {
+                println!("  + {}", describe(sprite));
+            }
+            for sprite in details["removed"].as_array().into_iter().flatten() {
+                println!("  - {}", describe(sprite));
+            }
+            for sprite in details["moved"].as_array().into_iter().flatten() {
+                println!("  moved {} to {}", describe(sprite), sprite["to"]);
+            }
+            for sprite in details["tint_changed"].as_array().into_iter().flatten() {
+                println!(
+                    "  tint {}: {} -> {}",
+                    describe(sprite),
+                    sprite["from"],
+                    sprite["to"]
+                );
+            }
+            for sprite in details["texture_changed"].as_array().into_iter().flatten() {
+                println!(
+                    "  texture {}: {} -> {}",
+                    describe(sprite),
+                    sprite["from"],
+                    sprite["to"]
+                );
+            }
+        }
+    }
+}
+
+fn load_map(path: &Path) -> Result<Map> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    Map::load(file).with_context(|| format!("reading {}", path.display()))
+}
+
+fn summary(diff: &MapDiff) -> Value {
+    json!({
+        "added": diff.added.len(),
+        "removed": diff.removed.len(),
+        "moved": diff.moved.len(),
+        "tint_changed": diff.tint_changed.len(),
+        "texture_changed": diff.texture_changed.len(),
+        "unchanged": diff.unchanged,
+    })
+}
+
+fn details(diff: &MapDiff) -> Value {
+    let moved = diff
+        .moved
+        .iter()
+        .map(|pair| {
+            let mut sprite = sprite_json(&pair.old);
+            sprite["to"] = json!(cell(&pair.new));
+            sprite
+        })
+        .collect::<Vec<_>>();
+    let tint_changed = diff
+        .tint_changed
+        .iter()
+        .map(|pair| {
+            let mut sprite = sprite_json(&pair.new);
+            sprite["from"] = json!(pair.old.color().as_linear_rgba_f32());
+            sprite["to"] = json!(pair.new.color().as_linear_rgba_f32());
+            sprite
+        })
+        .collect::<Vec<_>>();
+    let texture_changed = diff
+        .texture_changed
+        .iter()
+        .map(|(pair, old, new)| {
+            let mut sprite = sprite_json(&pair.new);
+            sprite["from"] = json!(old);
+            sprite["to"] = json!(new);
+            sprite
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "added": diff.added.iter().map(|sprite| sprite_json(sprite)).collect::<Vec<_>>(),
+        "removed": diff.removed.iter().map(|sprite| sprite_json(sprite)).collect::<Vec<_>>(),
+        "moved": moved,
+        "tint_changed": tint_changed,
+        "texture_changed": texture_changed,
+    })
+}
+
+#[inline]
+fn cell(sprite: &MapSprite) -> [i32; 3] {
+    position(sprite).to_array()
+}
+
+fn sprite_json(sprite: &MapSprite) -> Value {
+    json!({
+        "element": sprite.element_id,
+        "cell": cell(sprite),
+        "layer": sprite.layer,
+        "group_key": sprite.group_key,
+    })
+}
+
+fn describe(sprite: &Value) -> String {
+    format!(
+        "element {} at {} layer {} group {}",
+        sprite["element"], sprite["cell"], sprite["layer"], sprite["group_key"]
+    )
+}
+
+fn print_ids(title: &str, ids: &[i32]) {
+    if !ids.is_empty() {
+        let ids: Vec<String> = ids.iter().map(i32::to_string).collect();
+        println!("  {}: {}", title, ids.join(", "));
+    }
+}
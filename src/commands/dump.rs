@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use pico_args::Arguments;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+
+#[derive(Debug, Clone, Copy)]
+enum DumpFormat {
+    Json,
+    Ron,
+    MsgPack,
+}
+
+impl FromStr for DumpFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(str: &str) -> Result<Self> {
+        match str {
+            "json" => Ok(DumpFormat::Json),
+            "ron" => Ok(DumpFormat::Ron),
+            "msgpack" => Ok(DumpFormat::MsgPack),
+            other => Err(anyhow!(
+                "Unknown format {}, expected json, ron or msgpack",
+                other
+            )),
+        }
+    }
+}
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let map: i32 = pargs.value_from_str("--map")?;
+    let format: DumpFormat = pargs
+        .opt_value_from_str("--format")?
+        .unwrap_or(DumpFormat::Json);
+    let out: Option<PathBuf> = pargs.opt_value_from_str("--out")?;
+
+    let map = Map::load(File::open(paths.map(map))?)?;
+
+    // written to stdout unless an output file is given
+    let mut writer: Box<dyn Write> = match &out {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    match format {
+        DumpFormat::Json => serde_json::to_writer_pretty(&mut writer, &map)?,
+        DumpFormat::Ron => {
+            ron::ser::to_writer_pretty(&mut writer, &map, ron::ser::PrettyConfig::default())?
+        }
+        DumpFormat::MsgPack => rmp_serde::encode::write_named(&mut writer, &map)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use pico_args::Arguments;
+use vakfu_format::jar::Jar;
+use vakfu_format::tgam::Tgam;
+
+use super::GamePaths;
+use crate::offline::write_png;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let out: PathBuf = pargs.value_from_str("--out")?;
+    // textures are padded to a power of two, which is cropped unless asked otherwise
+    let keep_padding = pargs.contains("--keep-padding");
+
+    let jar = Jar::open(paths.gfx())?;
+    fs::create_dir_all(&out)?;
+
+    let mut written = 0;
+    let mut failed = 0;
+    // names that would write outside of the output directory aren't listed
+    for (name, entry) in jar.enclosed_names() {
+        let path = match texture_path(&entry) {
+            Some(path) => out.join(path),
+            None => continue,
+        };
+        match extract(&jar, &name, &path, keep_padding) {
+            Ok(()) => written += 1,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "wrote {} textures to {}, {} failed",
+        written,
+        out.display(),
+        failed
+    );
+    Ok(())
+}
+
+/// Path of the PNG of a texture entry relative to the output directory, `None` for entries
+/// that aren't textures.
+fn texture_path(entry: &Path) -> Option<PathBuf> {
+    let path = entry.strip_prefix("gfx").ok()?;
+    (path.extension()? == "tgam").then(|| path.with_extension("png"))
+}
+
+/// Writes a texture of the jar to a PNG, entries in subdirectories get them created.
+fn extract(jar: &Jar, name: &str, path: &Path, keep_padding: bool) -> Result<()> {
+    let bytes = jar
+        .read(name)
+        .with_context(|| format!("reading {}", name))?;
+    let (tgam, _) = Tgam::parse(&bytes).map_err(|err| err.in_entry(name))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let written = if keep_padding {
+        write_png(path, tgam.width(), tgam.height(), &tgam.rgba())
+    } else {
+        let (width, height) = (tgam.image_width(), tgam.image_height());
+        write_png(path, width, height, &tgam.image_rgba())
+    };
+    written.with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_paths() {
+        let path = |entry: &str| texture_path(Path::new(entry));
+        assert_eq!(path("gfx/12.tgam"), Some(PathBuf::from("12.png")));
+        assert_eq!(path("gfx/ui/12.tgam"), Some(PathBuf::from("ui/12.png")));
+        assert_eq!(path("gfx/12.png"), None);
+        assert_eq!(path("maps/12.tgam"), None);
+        assert_eq!(path("gfx"), None);
+    }
+}
@@ -0,0 +1,43 @@
+use std::fs::File;
+
+use anyhow::{anyhow, Result};
+use pico_args::Arguments;
+use serde_json::json;
+use vakfu_format::map::element::ElementLibrary;
+
+use super::index::{index_path, ElementIndex};
+use super::GamePaths;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let index_path = index_path(&mut pargs)?;
+    let element: Option<i32> = pargs.opt_value_from_str("--element")?;
+    let texture: Option<i32> = pargs.opt_value_from_str("--texture")?;
+
+    // maps that changed since the index was saved are scanned again first
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+    let mut index = ElementIndex::open(&index_path);
+    let stats = index.update(&paths, &lib)?;
+    if stats.scanned > 0 || stats.removed > 0 {
+        index.save(&index_path)?;
+    }
+
+    let (query, elements) = match (element, texture) {
+        (Some(id), None) => (json!({ "element": id }), vec![id]),
+        (None, Some(id)) => (json!({ "texture": id }), index.texture(id).collect()),
+        _ => return Err(anyhow!("Expected either --element or --texture")),
+    };
+    let mut placements = vec![];
+    for &element in &elements {
+        for (map, cells) in index.element(element) {
+            placements.push(json!({ "map": map, "element": element, "cells": cells }));
+        }
+    }
+    let result = json!({
+        "query": query,
+        "elements": elements,
+        "placements": placements,
+    });
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use pico_args::Arguments;
+use serde_json::{json, Value};
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+
+pub const DEFAULT_INDEX: &str = "vakfu-index.json";
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let index_path = index_path(&mut pargs)?;
+
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+    let mut index = ElementIndex::open(&index_path);
+    let stats = index.update(&paths, &lib)?;
+    index.save(&index_path)?;
+
+    println!(
+        "indexed {} maps to {}: {} scanned, {} unchanged, {} removed",
+        index.maps.len(),
+        index_path.display(),
+        stats.scanned,
+        stats.unchanged,
+        stats.removed
+    );
+    Ok(())
+}
+
+pub fn index_path(pargs: &mut Arguments) -> Result<PathBuf> {
+    let path = pargs.opt_value_from_str("--index")?;
+    Ok(path.unwrap_or_else(|| PathBuf::from(DEFAULT_INDEX)))
+}
+
+/// Cells of the map where elements are placed, along with the jar they were read from.
+#[derive(Debug)]
+struct MapEntry {
+    size: u64,
+    modified: u64,
+    elements: BTreeMap<i32, Vec<[i32; 3]>>,
+}
+
+/// Where each element is placed across all maps, and the elements using each texture.
+#[derive(Debug, Default)]
+pub struct ElementIndex {
+    maps: BTreeMap<i32, MapEntry>,
+    textures: BTreeMap<i32, BTreeSet<i32>>,
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateStats {
+    pub scanned: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+impl ElementIndex {
+    const VERSION: u64 = 1;
+
+    /// Reads the index saved at `path`, an index that is missing or can't be read starts empty.
+    pub fn open(path: &Path) -> Self {
+        File::open(path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .and_then(|value| Self::from_json(&value))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, &self.to_json())?;
+        Ok(())
+    }
+
+    /// Scans the maps whose jar changed since the last update and drops the ones that are gone.
+    pub fn update(&mut self, paths: &GamePaths, library: &ElementLibrary) -> Result<UpdateStats> {
+        let mut stats = UpdateStats::default();
+        let ids = paths.map_ids()?;
+
+        let before = self.maps.len();
+        self.maps.retain(|id, _| ids.binary_search(id).is_ok());
+        stats.removed = before - self.maps.len();
+
+        for id in ids {
+            let path = paths.map(id);
+            let metadata = fs::metadata(&path)?;
+            let size = metadata.len();
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64);
+            match self.maps.get(&id) {
+                Some(entry) if entry.size == size && entry.modified == modified => {
+                    stats.unchanged += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let map = match Map::load(File::open(&path)?) {
+                Ok(map) => map,
+                Err(err) => {
+                    eprintln!("failed to load map {}: {}", id, err);
+                    self.maps.remove(&id);
+                    continue;
+                }
+            };
+            let mut elements: BTreeMap<i32, Vec<[i32; 3]>> = BTreeMap::new();
+            for sprite in map.chunks().iter().flat_map(|chunk| &chunk.sprites) {
+                let cell = [sprite.cell_x, sprite.cell_y, sprite.cell_z as i32];
+                elements.entry(sprite.element_id).or_default().push(cell);
+            }
+            let entry = MapEntry {
+                size,
+                modified,
+                elements,
+            };
+            self.maps.insert(id, entry);
+            stats.scanned += 1;
+        }
+
+        // the library is cheap to go through again and may have changed as well
+        self.textures.clear();
+        for elem in library.elements() {
+            self.textures
+                .entry(elem.texture_id)
+                .or_default()
+                .insert(elem.id);
+        }
+        Ok(stats)
+    }
+
+    /// Maps the element is placed on, with the cells it's placed at.
+    pub fn element(&self, id: i32) -> impl Iterator<Item = (i32, &[[i32; 3]])> {
+        self.maps
+            .iter()
+            .filter_map(move |(map, entry)| Some((*map, entry.elements.get(&id)?.as_slice())))
+    }
+
+    /// Elements drawn from a texture.
+    pub fn texture(&self, id: i32) -> impl Iterator<Item = i32> + '_ {
+        self.textures.get(&id).into_iter().flatten().copied()
+    }
+
+    fn to_json(&self) -> Value {
+        let maps: serde_json::Map<String, Value> = self
+            .maps
+            .iter()
+            .map(|(id, entry)| {
+                let elements: serde_json::Map<String, Value> = entry
+                    .elements
+                    .iter()
+                    .map(|(id, cells)| (id.to_string(), json!(cells)))
+                    .collect();
+                let entry = json!({
+                    "size": entry.size,
+                    "modified": entry.modified,
+                    "elements": elements,
+                });
+                (id.to_string(), entry)
+            })
+            .collect();
+        let textures: serde_json::Map<String, Value> = self
+            .textures
+            .iter()
+            .map(|(id, elements)| (id.to_string(), json!(elements)))
+            .collect();
+        json!({
+            "version": Self::VERSION,
+            "maps": maps,
+            "textures": textures,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        if value.get("version")?.as_u64()? != Self::VERSION {
+            return None;
+        }
+        let mut maps = BTreeMap::new();
+        for (id, entry) in value.get("maps")?.as_object()? {
+            let mut elements = BTreeMap::new();
+            for (id, cells) in entry.get("elements")?.as_object()? {
+                let cells = serde_json::from_value(cells.clone()).ok()?;
+                elements.insert(id.parse().ok()?, cells);
+            }
+            let entry = MapEntry {
+                size: entry.get("size")?.as_u64()?,
+                modified: entry.get("modified")?.as_u64()?,
+                elements,
+            };
+            maps.insert(id.parse().ok()?, entry);
+        }
+        let mut textures = BTreeMap::new();
+        for (id, elements) in value.get("textures")?.as_object()? {
+            let elements = serde_json::from_value(elements.clone()).ok()?;
+            textures.insert(id.parse().ok()?, elements);
+        }
+        Some(Self { maps, textures })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use vakfu_format::map::chunk::{ChunkRect, MapChunk};
+    use vakfu_format::map::element::{ElementFlags, MapElement};
+    use vakfu_format::map::sprite::MapSprite;
+    use vakfu_format::map::version::FormatVersion;
+
+    use super::*;
+
+    fn library(elements: &[(i32, i32)]) -> ElementLibrary {
+        let elements = elements
+            .iter()
+            .map(|&(id, texture_id)| MapElement {
+                id,
+                origin_x: 0,
+                origin_y: 0,
+                img_width: 16,
+                img_height: 16,
+                texture_id,
+                flags: ElementFlags::new(),
+                visual_height: 0,
+                visibility_mask: 0,
+                export_mask: 0,
+                shader: 0,
+                animation: None,
+                ground_sound: 0,
+            })
+            .collect();
+        ElementLibrary::new(FormatVersion::Compact, elements)
+    }
+
+    /// Writes a map with a single cell holding a sprite of each element.
+    fn write_map(path: &Path, elements: &[i32]) {
+        let sprites = elements
+            .iter()
+            .map(|&element_id| MapSprite {
+                cell_z: 2,
+                element_id,
+                ..Default::default()
+            })
+            .collect();
+        let chunk = MapChunk {
+            map_x: 0,
+            map_y: 0,
+            min_x: 0,
+            min_y: 0,
+            min_z: 2,
+            max_x: 1,
+            max_y: 1,
+            max_z: 2,
+            rects: vec![ChunkRect {
+                min_x: 0,
+                max_x: 1,
+                min_y: 0,
+                max_y: 1,
+            }],
+            sprites,
+        };
+        let map = Map::new(FormatVersion::Compact, vec![("0_0".to_owned(), chunk)]);
+        map.save(File::create(path).unwrap()).unwrap();
+    }
+
+    fn game_dir(name: &str) -> GamePaths {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let paths = GamePaths::new(root);
+        fs::create_dir_all(paths.maps().join("gfx")).unwrap();
+        paths
+    }
+
+    fn placements(index: &ElementIndex, element: i32) -> Vec<(i32, Vec<[i32; 3]>)> {
+        index
+            .element(element)
+            .map(|(map, cells)| (map, cells.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let paths = game_dir("vakfu-index-json");
+        write_map(&paths.map(1), &[10, 11, 10]);
+        write_map(&paths.map(2), &[11]);
+        let lib = library(&[(10, 100), (11, 100), (12, 101)]);
+
+        let mut index = ElementIndex::default();
+        index.update(&paths, &lib).unwrap();
+        let path = paths.maps().join(DEFAULT_INDEX);
+        index.save(&path).unwrap();
+        let loaded = ElementIndex::open(&path);
+
+        assert_eq!(loaded.to_json(), index.to_json());
+        assert_eq!(placements(&loaded, 10), [(1, vec![[0, 0, 2], [0, 0, 2]])]);
+        assert_eq!(
+            placements(&loaded, 11),
+            [(1, vec![[0, 0, 2]]), (2, vec![[0, 0, 2]])]
+        );
+        assert_eq!(loaded.texture(100).collect::<Vec<_>>(), [10, 11]);
+        assert_eq!(loaded.texture(101).collect::<Vec<_>>(), [12]);
+
+        // an index of another version is dropped rather than misread
+        let mut json = index.to_json();
+        json["version"] = json!(ElementIndex::VERSION + 1);
+        assert!(ElementIndex::from_json(&json).is_none());
+    }
+
+    #[test]
+    fn rescan_modified() {
+        let paths = game_dir("vakfu-index-rescan");
+        write_map(&paths.map(1), &[10]);
+        write_map(&paths.map(2), &[10]);
+        let lib = library(&[(10, 100), (11, 100)]);
+
+        let mut index = ElementIndex::default();
+        let stats = index.update(&paths, &lib).unwrap();
+        assert_eq!((stats.scanned, stats.unchanged, stats.removed), (2, 0, 0));
+        let stats = index.update(&paths, &lib).unwrap();
+        assert_eq!((stats.scanned, stats.unchanged, stats.removed), (0, 2, 0));
+
+        // same size, only the modification time tells the map apart
+        write_map(&paths.map(1), &[11]);
+        let modified = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(paths.map(1))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::remove_file(paths.map(2)).unwrap();
+
+        let stats = index.update(&paths, &lib).unwrap();
+        assert_eq!((stats.scanned, stats.unchanged, stats.removed), (1, 0, 1));
+        assert!(placements(&index, 10).is_empty());
+        assert_eq!(placements(&index, 11), [(1, vec![[0, 0, 2]])]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use pico_args::Arguments;
+
+pub mod diff;
+#[cfg(feature = "serde")]
+pub mod dump;
+pub mod extract_textures;
+pub mod find;
+pub mod index;
+pub mod path;
+pub mod render;
+pub mod stats;
+pub mod tiled;
+pub mod tiles;
+
+/// Locations of the game files under an installation root.
+#[derive(Debug, Clone)]
+pub struct GamePaths {
+    root: PathBuf,
+}
+
+impl GamePaths {
+    #[inline]
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn from_args(pargs: &mut Arguments) -> Result<Self> {
+        let root: PathBuf = pargs.value_from_str("--path")?;
+        Ok(Self { root })
+    }
+
+    #[inline]
+    pub fn maps(&self) -> PathBuf {
+        self.root.join("contents").join("maps")
+    }
+
+    #[inline]
+    pub fn gfx(&self) -> PathBuf {
+        self.maps().join("gfx.jar")
+    }
+
+    #[inline]
+    pub fn map(&self, id: i32) -> PathBuf {
+        self.maps().join("gfx").join(format!("{}.jar", id))
+    }
+
+    /// Ids of all maps with a jar in the maps directory, in ascending order.
+    pub fn map_ids(&self) -> Result<Vec<i32>> {
+        let mut ids = vec![];
+        for entry in fs::read_dir(self.maps().join("gfx"))? {
+            let path = entry?.path();
+            if path.extension() == Some("jar".as_ref()) {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    #[inline]
+    pub fn library(&self) -> PathBuf {
+        self.maps().join("data.jar")
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs::File;
+
+use anyhow::Result;
+use glam::IVec2;
+use pico_args::Arguments;
+use serde_json::json;
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::path::find_path;
+use vakfu_format::map::walkability::Walkability;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+
+pub const DEFAULT_MAX_STEP: i16 = 1;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let map_id: i32 = pargs.value_from_str("--map")?;
+    let from = pargs.value_from_fn("--from", parse_cell)?;
+    let to = pargs.value_from_fn("--to", parse_cell)?;
+    let max_step: i16 = pargs
+        .opt_value_from_str("--max-step")?
+        .unwrap_or(DEFAULT_MAX_STEP);
+
+    let map = Map::load(File::open(paths.map(map_id))?)?;
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+    let walkability = Walkability::from_chunks(map.chunks(), &lib);
+
+    let path = find_path(&walkability, from, to, max_step).map(|path| {
+        path.iter()
+            .map(|cell| {
+                let z = walkability.get(*cell).map_or(0, |state| state.z);
+                json!({ "x": cell.x, "y": cell.y, "z": z })
+            })
+            .collect::<Vec<_>>()
+    });
+    let result = json!({
+        "map": map_id,
+        "from": [from.x, from.y],
+        "to": [to.x, to.y],
+        "max_step": max_step,
+        "reachable": path.is_some(),
+        "steps": path.as_ref().map(|path| path.len().saturating_sub(1)),
+        "path": path,
+    });
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+/// Parses a cell given as `x,y`.
+pub fn parse_cell(str: &str) -> Result<IVec2, String> {
+    let invalid = || format!("Invalid cell '{}', expected x,y", str);
+    let (x, y) = str.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse().map_err(|_| invalid())?;
+    let y = y.trim().parse().map_err(|_| invalid())?;
+    Ok(IVec2::new(x, y))
+}
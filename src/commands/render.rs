@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use pico_args::Arguments;
+use vakfu_format::jar::Jar;
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+use crate::offline::MapRenderer;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let map: i32 = pargs.value_from_str("--map")?;
+    let out: PathBuf = pargs.value_from_str("--out")?;
+    let scale: f32 = pargs.opt_value_from_str("--scale")?.unwrap_or(1.);
+
+    let map = Map::load(File::open(paths.map(map))?)?;
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+    let jar = Jar::open(paths.gfx())?;
+
+    let canvas = MapRenderer::new(&lib, &jar).render(&map, scale)?;
+    canvas.save_png(&out)?;
+    println!(
+        "wrote {}x{} image to {}",
+        canvas.width(),
+        canvas.height(),
+        out.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+use std::fs::File;
+
+use anyhow::Result;
+use glam::IVec3;
+use pico_args::Arguments;
+use serde_json::{json, Value};
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let map: i32 = pargs.value_from_str("--map")?;
+    let json = pargs.contains("--json");
+
+    let map = Map::load(File::open(paths.map(map))?)?;
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+
+    let stats = MapStats::new(&map, &lib);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats.to_json())?);
+    } else {
+        print!("{}", stats);
+    }
+    Ok(())
+}
+
+/// Counts over the chunks and sprites of a map, meant to be compared across game patches.
+#[derive(Debug, Default)]
+pub struct MapStats {
+    chunks: usize,
+    // cell bounds of the chunks
+    min: Option<IVec3>,
+    max: Option<IVec3>,
+    sprites: usize,
+    cell_z: Option<(i16, i16)>,
+    layers: BTreeMap<u8, usize>,
+    group_keys: BTreeMap<i32, usize>,
+    elements: BTreeMap<i32, usize>,
+    textures: BTreeSet<i32>,
+    animated_elements: usize,
+    // sprites whose element is not in the library, by element id
+    missing: BTreeMap<i32, usize>,
+}
+
+impl MapStats {
+    pub fn new(map: &Map, library: &ElementLibrary) -> Self {
+        let mut stats = MapStats::default();
+        for chunk in map.chunks() {
+            stats.chunks += 1;
+            let min = IVec3::new(chunk.min_x, chunk.min_y, chunk.min_z.into());
+            let max = IVec3::new(chunk.max_x, chunk.max_y, chunk.max_z.into());
+            stats.min = Some(stats.min.map_or(min, |prev| prev.min(min)));
+            stats.max = Some(stats.max.map_or(max, |prev| prev.max(max)));
+
+            for sprite in &chunk.sprites {
+                stats.sprites += 1;
+                stats.cell_z = Some(match stats.cell_z {
+                    Some((min, max)) => (min.min(sprite.cell_z), max.max(sprite.cell_z)),
+                    None => (sprite.cell_z, sprite.cell_z),
+                });
+                *stats.layers.entry(sprite.layer).or_default() += 1;
+                *stats.group_keys.entry(sprite.group_key).or_default() += 1;
+                *stats.elements.entry(sprite.element_id).or_default() += 1;
+                if library.get(sprite.element_id).is_none() {
+                    *stats.missing.entry(sprite.element_id).or_default() += 1;
+                }
+            }
+        }
+
+        for elem in stats.elements.keys().filter_map(|id| library.get(*id)) {
+            stats.textures.insert(elem.texture_id);
+            if elem.animation.is_some() {
+                stats.animated_elements += 1;
+            }
+        }
+        stats
+    }
+
+    /// Share of the distinct elements found in the library that are animated.
+    pub fn animated_share(&self) -> f32 {
+        let found = self.elements.len() - self.missing.len();
+        if found == 0 {
+            0.
+        } else {
+            self.animated_elements as f32 / found as f32
+        }
+    }
+
+    #[inline]
+    pub fn missing_sprites(&self) -> usize {
+        self.missing.values().sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "chunks": self.chunks,
+            "bounds": {
+                "min": self.min.map(|min| min.to_array()),
+                "max": self.max.map(|max| max.to_array()),
+            },
+            "sprites": self.sprites,
+            "cell_z": self.cell_z.map(|(min, max)| [min, max]),
+            "layers": counts_json(&self.layers),
+            "group_keys": counts_json(&self.group_keys),
+            "elements": counts_json(&self.elements),
+            "distinct_elements": self.elements.len(),
+            "distinct_textures": self.textures.len(),
+            "animated_elements": self.animated_elements,
+            "animated_share": self.animated_share(),
+            "missing_elements": counts_json(&self.missing),
+            "missing_sprites": self.missing_sprites(),
+        })
+    }
+}
+
+impl Display for MapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "chunks: {}", self.chunks)?;
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            writeln!(f, "cell bounds: {} to {}", min, max)?;
+        }
+        writeln!(f, "sprites: {}", self.sprites)?;
+        if let Some((min, max)) = self.cell_z {
+            writeln!(f, "cell_z: {} to {}", min, max)?;
+        }
+        writeln!(
+            f,
+            "elements: {} distinct, {} animated ({:.1}%), {} textures",
+            self.elements.len(),
+            self.animated_elements,
+            self.animated_share() * 100.,
+            self.textures.len()
+        )?;
+        writeln!(
+            f,
+            "missing from the library: {} elements, {} sprites",
+            self.missing.len(),
+            self.missing_sprites()
+        )?;
+        write_counts(f, "sprites per layer", &self.layers)?;
+        write_counts(f, "sprites per group key", &self.group_keys)?;
+        write_counts(f, "sprites per element", &self.elements)?;
+        write_counts(f, "sprites per missing element", &self.missing)
+    }
+}
+
+fn counts_json<K: Display>(counts: &BTreeMap<K, usize>) -> Value {
+    let counts: serde_json::Map<String, Value> = counts
+        .iter()
+        .map(|(key, count)| (key.to_string(), json!(count)))
+        .collect();
+    Value::Object(counts)
+}
+
+fn write_counts<K: Display>(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    counts: &BTreeMap<K, usize>,
+) -> fmt::Result {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "{}:", title)?;
+    for (key, count) in counts {
+        writeln!(f, "  {}: {}", key, count)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use pico_args::Arguments;
+use vakfu_format::jar::Jar;
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+use crate::offline::tiled::TiledMap;
+use crate::offline::Textures;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let map: i32 = pargs.value_from_str("--map")?;
+    let out: PathBuf = pargs.value_from_str("--out")?;
+
+    let map = Map::load(File::open(paths.map(map))?)?;
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+    let jar = Jar::open(paths.gfx())?;
+
+    let tiled = TiledMap::new(&map, &lib);
+    let tiles = tiled.save(&lib, &mut Textures::new(&jar), &out)?;
+    println!("wrote map with {} tiles to {}", tiles, out.display());
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use pico_args::Arguments;
+use vakfu_format::jar::Jar;
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::Map;
+
+use super::GamePaths;
+use crate::offline::tiles::{export_tiles, map_bounds, TileGrid};
+use crate::offline::MapRenderer;
+
+pub fn run(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let map: i32 = pargs.value_from_str("--map")?;
+    let out: PathBuf = pargs.value_from_str("--out")?;
+    let tile_size: u32 = pargs.opt_value_from_str("--tile-size")?.unwrap_or(256);
+    let scale: f32 = pargs.opt_value_from_str("--scale")?.unwrap_or(1.);
+
+    let map = Map::load(File::open(paths.map(map))?)?;
+    let lib = ElementLibrary::load(File::open(paths.library())?)?;
+    let jar = Jar::open(paths.gfx())?;
+
+    let bounds = map_bounds(&map).ok_or_else(|| anyhow!("Map has no chunks"))?;
+    let grid = TileGrid::new(bounds, scale, tile_size)?;
+    let mut renderer = MapRenderer::new(&lib, &jar);
+    let sprites = renderer.sprites(&map);
+    let written = export_tiles(&grid, &out, |canvas| renderer.draw(&sprites, canvas))?;
+    println!("wrote {} tiles to {}", written, out.display());
+    Ok(())
+}
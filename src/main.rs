@@ -2,23 +2,36 @@ use std::env;
 use std::fs::File;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use assets::jar::JarAssetIo;
 use assets::tgam::TgamLoader;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
-use map::element::ElementLibrary;
-use map::Map;
+use commands::GamePaths;
 use pico_args::Arguments;
+use systems::blend::{blend_animation_system, BlendPlugin};
+use systems::browser::{map_browser_system, MapBrowser};
 use systems::camera::{camera_controller_system, camera_system, CameraController};
-use systems::render::{animation_system, map_chunk_view_system, visibility_system};
+use systems::diff::{diff_tint_system, map_diff_system, MapDiffView};
+use systems::library::{library_browser_system, LibraryBrowser};
+use systems::overlay::overlay_setup_system;
+use systems::path::{path_tool_system, PathTool};
+use systems::picking::{hover_system, picking_system, HoveredCell, Selection};
+use systems::render::{animation_system, map_chunk_view_system, visibility_system, ViewRect};
 use systems::settings::{settings_system, Settings};
 use systems::setup::setup_system;
-use systems::ui::ui_system;
+use systems::stream::{chunk_stream_system, ChunkStream};
+use systems::ui::{
+    cursor_readout_system, inspector_system, path_tool_ui_system, render_order_system, ui_system,
+};
+use vakfu_format::jar::Jar;
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::source::MapSource;
 
 mod assets;
-mod map;
+mod commands;
+mod offline;
 mod systems;
 
 fn main() -> Result<()> {
@@ -26,46 +39,125 @@ fn main() -> Result<()> {
     env::set_var("RUST_BACKTRACE", "1");
 
     let mut pargs = Arguments::from_env();
-    let game_path: PathBuf = pargs.value_from_str("--path")?;
-    let map: i32 = pargs.value_from_str("--map")?;
+    match pargs.subcommand()?.as_deref() {
+        None => run_viewer(pargs),
+        Some("diff") => commands::diff::run(pargs),
+        #[cfg(feature = "serde")]
+        Some("dump") => commands::dump::run(pargs),
+        #[cfg(not(feature = "serde"))]
+        Some("dump") => Err(anyhow!("The dump command requires the serde feature")),
+        Some("extract-textures") => commands::extract_textures::run(pargs),
+        Some("find") => commands::find::run(pargs),
+        Some("index") => commands::index::run(pargs),
+        Some("path") => commands::path::run(pargs),
+        Some("render") => commands::render::run(pargs),
+        Some("stats") => commands::stats::run(pargs),
+        Some("tiled") => commands::tiled::run(pargs),
+        Some("tiles") => commands::tiles::run(pargs),
+        Some(other) => Err(anyhow!("Unknown command: {}", other)),
+    }
+}
+
+fn run_viewer(mut pargs: Arguments) -> Result<()> {
+    let paths = GamePaths::from_args(&mut pargs)?;
+    let maps = paths.map_ids()?;
+    let map: i32 = match pargs.opt_value_from_str("--map")? {
+        Some(map) => map,
+        None => *maps
+            .first()
+            .ok_or_else(|| anyhow!("No maps found in {}", paths.maps().display()))?,
+    };
 
-    let maps_path = game_path.join("contents").join("maps");
-    let gfx_path = maps_path.join("gfx.jar");
-    let map_path = maps_path.join("gfx").join(format!("{}.jar", map));
-    let lib_path = maps_path.join("data.jar");
+    let gfx_path = paths.gfx();
+    let map_path = paths.map(map);
+    let lib_path = paths.library();
 
     println!("gfx_path is {}\n", gfx_path.display());
     println!("map_path is {}\n", map_path.display());
     println!("lib_path is {}\n", lib_path.display());
 
-    let map = Map::load(File::open(map_path)?)?;
+    let source = MapSource::open(map_path)?;
     let lib = ElementLibrary::load(File::open(lib_path)?)?;
 
+    let diff_view = match pargs.opt_value_from_str::<_, PathBuf>("--old")? {
+        Some(root) => {
+            let old = GamePaths::new(root);
+            let old_gfx = Jar::open(old.gfx())?;
+            let old_lib = ElementLibrary::load(File::open(old.library())?)?;
+            // the viewer's library is a resource, the comparisons run in the background
+            MapDiffView::new(old, old_gfx, old_lib, lib.clone())
+        }
+        None => MapDiffView::default(),
+    };
+
     App::new()
         .add_plugins_with(DefaultPlugins, |group| {
             group.add_before::<bevy::asset::AssetPlugin, _>(JarAssetIo::plugin(gfx_path))
         })
         .add_plugin(EguiPlugin)
+        .add_plugin(BlendPlugin)
         .add_plugin(LogDiagnosticsPlugin::default())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
         .init_asset_loader::<TgamLoader>()
         .insert_resource(Settings::default())
         .insert_resource(CameraController::default())
         .insert_resource(lib)
-        .insert_resource(map)
+        .insert_resource(ViewRect::default())
+        .insert_resource(Selection::default())
+        .insert_resource(HoveredCell::default())
+        .insert_resource(PathTool::default())
+        .insert_resource(ChunkStream::new(source))
+        .insert_resource(MapBrowser::new(paths, maps, map))
+        .insert_resource(LibraryBrowser::default())
+        .insert_resource(diff_view)
         .add_startup_system(setup_system)
+        .add_startup_system(overlay_setup_system)
         .add_system(settings_system.label("settings"))
         .add_system(ui_system.label("ui"))
+        .add_system(inspector_system.label("inspector").after("ui"))
+        .add_system(picking_system.label("picking").before("inspector"))
+        .add_system(hover_system.label("hover").after("camera"))
+        .add_system(cursor_readout_system.after("hover").after("ui"))
+        .add_system(render_order_system.after("camera").after("ui"))
+        .add_system(path_tool_ui_system.label("path_ui").after("ui"))
+        .add_system(
+            path_tool_system
+                .after("path_ui")
+                .after("hover")
+                .after("browser"),
+        )
+        .add_system(library_browser_system.after("ui").before("browser"))
+        .add_system(map_browser_system.label("browser").after("ui"))
         .add_system(camera_controller_system.label("camera_control"))
-        .add_system(camera_system.label("camera").after("camera_control"))
+        .add_system(
+            camera_system
+                .label("camera")
+                .after("camera_control")
+                .after("browser"),
+        )
         .add_system(map_chunk_view_system.label("chunk_view").after("camera"))
+        // sprites are spawned with the filters as they are after the ui changed them
+        .add_system(
+            chunk_stream_system
+                .label("stream")
+                .after("chunk_view")
+                .after("ui"),
+        )
         .add_system(
             visibility_system
                 .label("visibility")
-                .after("chunk_view")
+                .after("stream")
                 .after("settings"),
         )
+        .add_system(map_diff_system.label("diff").after("browser"))
+        .add_system(
+            diff_tint_system
+                .after("diff")
+                .after("settings")
+                .after("stream"),
+        )
         .add_system(animation_system.label("animation").after("visibility"))
+        .add_system(blend_animation_system.after("visibility"))
         .run();
 
     Ok(())
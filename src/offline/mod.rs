@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use glam::Vec2;
+use itertools::Itertools;
+use vakfu_format::error::FormatError;
+use vakfu_format::jar::Jar;
+use vakfu_format::map::element::{BlendMode, ElementLibrary, MapElement};
+use vakfu_format::map::sprite::MapSprite;
+use vakfu_format::map::{compute_z_orders, Map};
+use vakfu_format::rect::Rect;
+use vakfu_format::tgam::Tgam;
+
+pub mod tiled;
+pub mod tiles;
+
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Texture {
+    pub fn decode(bytes: &[u8]) -> Result<Self, FormatError> {
+        let (tgam, _) = Tgam::parse(bytes)?;
+        let texture = Texture {
+            width: tgam.width(),
+            height: tgam.height(),
+            pixels: tgam.rgba(),
+        };
+        Ok(texture)
+    }
+
+    #[inline]
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    #[inline]
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let idx = (y as usize * self.width as usize + x as usize) * 4;
+        match self.pixels.get(idx..idx + 4) {
+            Some([r, g, b, a]) if x < self.width => [*r, *g, *b, *a],
+            _ => [0; 4],
+        }
+    }
+
+    /// RGBA pixels of an atlas rect, which is relative to the element image size.
+    pub fn crop(&self, rect: Rect, image_size: Vec2) -> (u32, u32, Vec<u8>) {
+        let size = rect.size();
+        let (width, height) = (size.x as u32, size.y as u32);
+        let texel_scale = self.size() / image_size;
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let u = (rect.min.x + x as f32 + 0.5) * texel_scale.x;
+                let v = (rect.min.y + y as f32 + 0.5) * texel_scale.y;
+                pixels.extend(self.pixel(u as u32, v as u32));
+            }
+        }
+        (width, height, pixels)
+    }
+}
+
+/// Textures of a gfx jar, decoded on first use.
+pub struct Textures<'a> {
+    jar: &'a Jar,
+    textures: HashMap<i32, Option<Texture>>,
+}
+
+impl<'a> Textures<'a> {
+    pub fn new(jar: &'a Jar) -> Self {
+        Self {
+            jar,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// The texture with the given id, `None` when it's missing or can't be decoded.
+    pub fn get(&mut self, id: i32) -> Option<&Texture> {
+        let jar = self.jar;
+        self.textures
+            .entry(id)
+            .or_insert_with(|| {
+                let path = format!("gfx/{}.tgam", id);
+                let bytes = jar.read(&path).ok()?;
+                match Texture::decode(&bytes).map_err(|err| err.in_entry(&path)) {
+                    Ok(texture) => Some(texture),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+}
+
+/// An RGBA image covering a rectangle of screen space.
+pub struct Canvas {
+    origin: Vec2,
+    scale: f32,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(bounds: Rect, scale: f32) -> Self {
+        let size = (bounds.size() * scale).ceil();
+        let origin = Vec2::new(bounds.min.x, bounds.max.y);
+        Self::with_size(origin, scale, size.x.max(1.) as u32, size.y.max(1.) as u32)
+    }
+
+    /// A transparent canvas of the given size in pixels, with `origin` at its top-left corner.
+    pub fn with_size(origin: Vec2, scale: f32, width: u32, height: u32) -> Self {
+        Self {
+            origin,
+            scale,
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Screen position of the top-left corner of the canvas.
+    #[inline]
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    /// Number of canvas pixels per screen unit.
+    #[inline]
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Screen rectangle covered by the canvas.
+    pub fn bounds(&self) -> Rect {
+        let size = Vec2::new(self.width as f32, self.height as f32) / self.scale;
+        Rect {
+            min: Vec2::new(self.origin.x, self.origin.y - size.y),
+            max: Vec2::new(self.origin.x + size.x, self.origin.y),
+        }
+    }
+
+    /// Converts a screen position into canvas pixel coordinates.
+    #[inline]
+    pub fn to_pixel(&self, pos: Vec2) -> Vec2 {
+        Vec2::new(pos.x - self.origin.x, self.origin.y - pos.y) * self.scale
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_png(path, self.width, self.height, &self.pixels)
+    }
+
+    /// Copies a rectangle of pixels, padding the parts outside of the canvas with transparency.
+    pub fn region(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        let cols = width.min(self.width.saturating_sub(x)) as usize;
+        if cols == 0 {
+            return pixels;
+        }
+        for row in 0..height.min(self.height.saturating_sub(y)) as usize {
+            let src = ((y as usize + row) * self.width as usize + x as usize) * 4;
+            let dst = row * width as usize * 4;
+            pixels[dst..dst + cols * 4].copy_from_slice(&self.pixels[src..src + cols * 4]);
+        }
+        pixels
+    }
+
+    /// Adds the rows of a canvas of the same width below the rows of this one.
+    pub fn append(&mut self, below: &Canvas) {
+        assert_eq!(
+            self.width, below.width,
+            "appending a canvas of another width"
+        );
+        self.pixels.extend_from_slice(&below.pixels);
+        self.height += below.height;
+    }
+
+    /// Returns a canvas of half the resolution, averaging each 2x2 block of pixels.
+    pub fn downsample(&self) -> Canvas {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+
+        for y in 0..height {
+            for x in 0..width {
+                // colors are weighted by alpha so transparent pixels don't darken the edges
+                let mut color = [0u32; 3];
+                let mut alpha = 0u32;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (sx, sy) = (x * 2 + dx, y * 2 + dy);
+                    if sx >= self.width || sy >= self.height {
+                        continue;
+                    }
+                    let idx = (sy as usize * self.width as usize + sx as usize) * 4;
+                    let src = &self.pixels[idx..idx + 4];
+                    for (sum, c) in color.iter_mut().zip(src) {
+                        *sum += *c as u32 * src[3] as u32;
+                    }
+                    alpha += src[3] as u32;
+                }
+                let idx = (y as usize * width as usize + x as usize) * 4;
+                let dst = &mut pixels[idx..idx + 4];
+                for (out, sum) in dst.iter_mut().zip(color) {
+                    *out = sum.checked_div(alpha).unwrap_or(0) as u8;
+                }
+                dst[3] = (alpha / 4) as u8;
+            }
+        }
+        Canvas {
+            origin: self.origin,
+            scale: self.scale / 2.,
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn blend(&mut self, x: i32, y: i32, texel: [u8; 4], tint: [f32; 4], mode: BlendMode) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let alpha = texel[3] as f32 / 255. * tint[3];
+        if alpha <= 0. {
+            return;
+        }
+        let idx = (y as usize * self.width as usize + x as usize) * 4;
+        let dst = &mut self.pixels[idx..idx + 4];
+        for c in 0..3 {
+            let src = (texel[c] as f32 / 255. * tint[c]).min(1.);
+            let prev = dst[c] as f32 / 255.;
+            let out = match mode {
+                BlendMode::Alpha => src * alpha + prev * (1. - alpha),
+                BlendMode::Additive => (prev + src * alpha).min(1.),
+                BlendMode::Multiply => prev * (src * alpha + 1. - alpha),
+            };
+            dst[c] = (out * 255.).round() as u8;
+        }
+        let out = alpha + dst[3] as f32 / 255. * (1. - alpha);
+        dst[3] = (out.min(1.) * 255.).round() as u8;
+    }
+
+    fn draw(&mut self, texture: &Texture, quad: &SpriteQuad) {
+        let top_left = self.to_pixel(quad.position).round();
+        let size = (quad.rect.size() * self.scale).round();
+        // texture atlases address the texture relative to the element image size
+        let texel_scale = texture.size() / quad.image_size;
+
+        for j in 0..size.y as i32 {
+            for i in 0..size.x as i32 {
+                let offset = Vec2::new(i as f32 + 0.5, j as f32 + 0.5) / self.scale;
+                let u = if quad.flip_x {
+                    quad.rect.max.x - offset.x
+                } else {
+                    quad.rect.min.x + offset.x
+                };
+                let v = quad.rect.min.y + offset.y;
+                let texel = texture.pixel((u * texel_scale.x) as u32, (v * texel_scale.y) as u32);
+                self.blend(
+                    top_left.x as i32 + i,
+                    top_left.y as i32 + j,
+                    texel,
+                    quad.color,
+                    quad.blend,
+                );
+            }
+        }
+    }
+}
+
+/// A sprite placed the same way the viewer spawns it: anchored at its top-left corner.
+struct SpriteQuad {
+    texture_id: i32,
+    position: Vec2,
+    rect: Rect,
+    image_size: Vec2,
+    flip_x: bool,
+    color: [f32; 4],
+    blend: BlendMode,
+    z_order: usize,
+}
+
+impl SpriteQuad {
+    fn new(sprite: &MapSprite, element: &MapElement, z_order: usize) -> Self {
+        let rect = element
+            .animation
+            .as_ref()
+            .and_then(|frames| frames.frame_rects.first().copied())
+            .unwrap_or_else(|| element.rect());
+        Self {
+            texture_id: element.texture_id,
+            position: sprite.anchor_position(element),
+            rect,
+            image_size: element.image_size(),
+            flip_x: element.flags.is_flip(),
+            color: sprite.color().as_linear_rgba_f32(),
+            blend: element.blend_mode(),
+            z_order,
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        let size = self.rect.size();
+        Rect {
+            min: Vec2::new(self.position.x, self.position.y - size.y),
+            max: Vec2::new(self.position.x + size.x, self.position.y),
+        }
+    }
+}
+
+/// Sprites of a map placed for rendering, in drawing order.
+pub struct MapSprites {
+    quads: Vec<SpriteQuad>,
+}
+
+impl MapSprites {
+    pub fn new(map: &Map, library: &ElementLibrary) -> Self {
+        let z_orders = compute_z_orders(map);
+        let quads = map
+            .chunks()
+            .iter()
+            .flat_map(|chunk| &chunk.sprites)
+            .zip(z_orders)
+            .filter_map(|(sprite, z_order)| {
+                let elem = library.get(sprite.element_id)?;
+                Some(SpriteQuad::new(sprite, elem, z_order))
+            })
+            .sorted_by_key(|quad| quad.z_order)
+            .collect_vec();
+        Self { quads }
+    }
+}
+
+/// Composites a whole map on the CPU, without a window or a GPU.
+pub struct MapRenderer<'a> {
+    library: &'a ElementLibrary,
+    textures: Textures<'a>,
+}
+
+impl<'a> MapRenderer<'a> {
+    pub fn new(library: &'a ElementLibrary, jar: &'a Jar) -> Self {
+        Self {
+            library,
+            textures: Textures::new(jar),
+        }
+    }
+
+    /// Renders the whole map, sized to fit every sprite.
+    pub fn render(&mut self, map: &Map, scale: f32) -> Result<Canvas> {
+        let sprites = self.sprites(map);
+        let bounds = sprites
+            .quads
+            .iter()
+            .map(SpriteQuad::bounds)
+            .reduce(|a, b| Rect {
+                min: a.min.min(b.min),
+                max: a.max.max(b.max),
+            })
+            .ok_or_else(|| anyhow!("Map has no sprites to render"))?;
+
+        Ok(self.draw(&sprites, Canvas::new(bounds, scale)))
+    }
+
+    /// Places the sprites of a map, so that parts of it can be drawn one after the other.
+    #[inline]
+    pub fn sprites(&self, map: &Map) -> MapSprites {
+        MapSprites::new(map, self.library)
+    }
+
+    /// Draws the sprites that overlap the canvas onto it.
+    pub fn draw(&mut self, sprites: &MapSprites, mut canvas: Canvas) -> Canvas {
+        // quads are placed on whole pixels, keep the ones that may round onto the canvas
+        let margin = Vec2::splat(1. / canvas.scale);
+        let bounds = canvas.bounds();
+        let bounds = Rect {
+            min: bounds.min - margin,
+            max: bounds.max + margin,
+        };
+        for quad in &sprites.quads {
+            if !overlaps(quad.bounds(), bounds) {
+                continue;
+            }
+            if let Some(texture) = self.textures.get(quad.texture_id) {
+                canvas.draw(texture, quad);
+            }
+        }
+        canvas
+    }
+}
+
+#[inline]
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.min.x < b.max.x && b.min.x < a.max.x && a.min.y < b.max.y && b.min.y < a.max.y
+}
+
+pub fn write_png<P: AsRef<Path>>(path: P, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use vakfu_format::map::chunk::MapChunk;
+    use vakfu_format::map::element::ElementFlags;
+    use vakfu_format::map::version::FormatVersion;
+
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0; 4];
+    const WHITE: [f32; 4] = [1.; 4];
+
+    fn texture(width: u32, height: u32, pixels: &[[u8; 4]]) -> Texture {
+        Texture {
+            width,
+            height,
+            pixels: pixels.concat(),
+        }
+    }
+
+    fn canvas(width: u32, height: u32, pixels: &[[u8; 4]]) -> Canvas {
+        let mut canvas = Canvas::with_size(Vec2::ZERO, 1., width, height);
+        canvas.pixels = pixels.concat();
+        canvas
+    }
+
+    fn pixels(canvas: &Canvas) -> Vec<[u8; 4]> {
+        canvas
+            .pixels
+            .chunks_exact(4)
+            .map(|px| [px[0], px[1], px[2], px[3]])
+            .collect()
+    }
+
+    fn quad(width: f32, flip_x: bool) -> SpriteQuad {
+        SpriteQuad {
+            texture_id: 0,
+            position: Vec2::ZERO,
+            rect: Rect {
+                min: Vec2::ZERO,
+                max: Vec2::new(width, 1.),
+            },
+            image_size: Vec2::new(width, 1.),
+            flip_x,
+            color: WHITE,
+            blend: BlendMode::Alpha,
+            z_order: 0,
+        }
+    }
+
+    #[test]
+    fn alpha_over() {
+        let mut canvas = canvas(2, 1, &[BLUE, CLEAR]);
+        canvas.blend(0, 0, [255, 0, 0, 128], WHITE, BlendMode::Alpha);
+        canvas.blend(
+            1,
+            0,
+            [255, 255, 255, 255],
+            [0.5, 1., 1., 0.5],
+            BlendMode::Alpha,
+        );
+        // outside of the canvas
+        canvas.blend(2, 0, RED, WHITE, BlendMode::Alpha);
+        canvas.blend(-1, 0, RED, WHITE, BlendMode::Alpha);
+        assert_eq!(pixels(&canvas), [[128, 0, 127, 255], [64, 128, 128, 128]]);
+    }
+
+    #[test]
+    fn additive_and_multiply() {
+        let gray = [100, 100, 100, 255];
+        let mut canvas = canvas(2, 1, &[gray, gray]);
+        canvas.blend(0, 0, RED, WHITE, BlendMode::Additive);
+        canvas.blend(1, 0, RED, WHITE, BlendMode::Multiply);
+        assert_eq!(pixels(&canvas), [[255, 100, 100, 255], [100, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn flipped_quad() {
+        let texture = texture(3, 1, &[RED, GREEN, BLUE]);
+        let mut straight = canvas(3, 1, &[CLEAR; 3]);
+        straight.draw(&texture, &quad(3., false));
+        assert_eq!(pixels(&straight), [RED, GREEN, BLUE]);
+
+        let mut flipped = canvas(3, 1, &[CLEAR; 3]);
+        flipped.draw(&texture, &quad(3., true));
+        assert_eq!(pixels(&flipped), [BLUE, GREEN, RED]);
+    }
+
+    #[test]
+    fn padded_texture() {
+        // twice the size of the element image, the rects are scaled to it
+        let mut pixels = vec![CLEAR; 8 * 2];
+        pixels[8 + 3] = RED;
+        pixels[8 + 5] = GREEN;
+        let texture = texture(8, 2, &pixels);
+        let rect = Rect {
+            min: Vec2::new(1., 0.),
+            max: Vec2::new(3., 1.),
+        };
+        let (width, height, cropped) = texture.crop(rect, Vec2::new(4., 1.));
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(cropped, [RED, GREEN].concat());
+    }
+
+    #[test]
+    fn downsample() {
+        #[rustfmt::skip]
+        let canvas = canvas(3, 2, &[
+            RED, [0, 0, 255, 0], BLUE,
+            GREEN, CLEAR, BLUE,
+        ]);
+        let half = canvas.downsample();
+        assert_eq!((half.width(), half.height()), (2, 1));
+        assert_eq!(half.scale(), 0.5);
+        // transparent pixels don't count towards the color, only the alpha
+        assert_eq!(pixels(&half), [[127, 127, 0, 127], [0, 0, 255, 127]]);
+    }
+
+    #[test]
+    fn region() {
+        let canvas = canvas(2, 2, &[RED, GREEN, BLUE, RED]);
+        assert_eq!(canvas.region(0, 0, 2, 1), [RED, GREEN].concat());
+        assert_eq!(
+            canvas.region(1, 1, 2, 2),
+            [RED, CLEAR, CLEAR, CLEAR].concat()
+        );
+        assert_eq!(canvas.region(5, 0, 1, 1), CLEAR);
+    }
+
+    #[test]
+    fn drawing_order() {
+        let element = |id| MapElement {
+            id,
+            origin_x: 0,
+            origin_y: 0,
+            img_width: 2,
+            img_height: 2,
+            texture_id: id,
+            flags: ElementFlags::new(),
+            visual_height: 0,
+            visibility_mask: 0,
+            export_mask: 0,
+            shader: 0,
+            animation: None,
+            ground_sound: 0,
+        };
+        let library = ElementLibrary::new(FormatVersion::Compact, vec![element(1), element(2)]);
+        let sprite = |element_id, altitude_order| MapSprite {
+            element_id,
+            altitude_order,
+            ..Default::default()
+        };
+        let chunk = MapChunk {
+            map_x: 0,
+            map_y: 0,
+            min_x: 0,
+            min_y: 0,
+            min_z: 0,
+            max_x: 1,
+            max_y: 1,
+            max_z: 0,
+            rects: vec![],
+            // stored in the reverse of the drawing order, the last one isn't in the library
+            sprites: vec![sprite(2, 1), sprite(1, 0), sprite(3, 2)],
+        };
+        let map = Map::new(FormatVersion::Compact, vec![("0_0".to_owned(), chunk)]);
+
+        let sprites = MapSprites::new(&map, &library);
+        let order = sprites
+            .quads
+            .iter()
+            .map(|quad| quad.texture_id)
+            .collect_vec();
+        assert_eq!(order, [1, 2]);
+
+        let textures = [
+            (1, texture(2, 2, &[RED; 4])),
+            (2, texture(2, 2, &[BLUE; 4])),
+        ];
+        let textures: HashMap<_, _> = textures.into_iter().collect();
+        let mut canvas = Canvas::new(sprites.quads[0].bounds(), 1.);
+        for quad in &sprites.quads {
+            canvas.draw(&textures[&quad.texture_id], quad);
+        }
+        assert_eq!(pixels(&canvas), [BLUE; 4]);
+    }
+}
@@ -0,0 +1,632 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use glam::{IVec2, Vec2};
+use itertools::Itertools;
+use serde_json::{json, Value};
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::{compute_z_orders, Map, CELL_HEIGHT, CELL_WIDTH};
+
+use super::{write_png, Textures};
+
+const TILED_VERSION: &str = "1.8";
+// horizontal flip bit of a tile gid
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+
+/// Tile of the exported tileset, one per frame of each element placed on the map.
+struct Tile {
+    id: u32,
+    element: i32,
+    frame: usize,
+    width: u32,
+    height: u32,
+    // frames of animated elements, set on the tile of their first frame
+    animation: Vec<(u32, u16)>,
+}
+
+impl Tile {
+    #[inline]
+    fn image(&self) -> String {
+        format!("tiles/{}_{}.png", self.element, self.frame)
+    }
+}
+
+struct Object {
+    id: u32,
+    gid: u32,
+    position: Vec2,
+    width: u32,
+    height: u32,
+    properties: Vec<(&'static str, Property)>,
+}
+
+enum Property {
+    Int(i64),
+    Color([u8; 4]),
+}
+
+impl Property {
+    fn kind(&self) -> &'static str {
+        match self {
+            Property::Int(_) => "int",
+            Property::Color(_) => "color",
+        }
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            Property::Int(value) => json!(value),
+            Property::Color(_) => json!(self.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Property::Int(value) => write!(f, "{}", value),
+            Property::Color([r, g, b, a]) => write!(f, "#{:02x}{:02x}{:02x}{:02x}", a, r, g, b),
+        }
+    }
+}
+
+/// Isometric Tiled map with an object layer for each sprite layer. Sprites are tile objects
+/// of a single tileset made of the frames of the elements, ordered as `compute_z_orders`.
+pub struct TiledMap {
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+    layers: Vec<(u8, Vec<Object>)>,
+}
+
+impl TiledMap {
+    pub fn new(map: &Map, library: &ElementLibrary) -> Self {
+        let sprites = map
+            .chunks()
+            .iter()
+            .flat_map(|chunk| &chunk.sprites)
+            .zip(compute_z_orders(map))
+            .filter_map(|(sprite, z_order)| {
+                Some((sprite, library.get(sprite.element_id)?, z_order))
+            })
+            .sorted_by_key(|(_, _, z_order)| *z_order)
+            .collect_vec();
+
+        let cells = sprites
+            .iter()
+            .map(|(sprite, _, _)| IVec2::new(sprite.cell_x, sprite.cell_y));
+        let min = cells.clone().reduce(IVec2::min).unwrap_or_default();
+        let max = cells.reduce(IVec2::max).unwrap_or_default();
+
+        // tile of the first frame of each element
+        let mut tiles = vec![];
+        let mut first_tiles = BTreeMap::new();
+        for (_, elem, _) in &sprites {
+            first_tiles.entry(elem.id).or_insert_with(|| {
+                let first = tiles.len() as u32;
+                let rects = elem
+                    .animation
+                    .as_ref()
+                    .map(|frames| frames.frame_rects.clone())
+                    .unwrap_or_else(|| vec![elem.rect()]);
+                for (frame, rect) in rects.iter().enumerate() {
+                    let size = rect.size();
+                    tiles.push(Tile {
+                        id: tiles.len() as u32,
+                        element: elem.id,
+                        frame,
+                        width: size.x as u32,
+                        height: size.y as u32,
+                        animation: vec![],
+                    });
+                }
+                if let Some(frames) = &elem.animation {
+                    let durations = frames.frame_durations.iter().copied();
+                    tiles[first as usize].animation = (first..).zip(durations).collect();
+                }
+                first
+            });
+        }
+
+        let mut layers: BTreeMap<u8, Vec<Object>> = BTreeMap::new();
+        for (sprite, elem, z_order) in &sprites {
+            let tile = &tiles[first_tiles[&elem.id] as usize];
+            let mut gid = tile.id + 1;
+            if elem.flags.is_flip() {
+                gid |= FLIPPED_HORIZONTALLY;
+            }
+            let color = sprite
+                .color()
+                .as_rgba_f32()
+                .map(|c| (c * 255.).round() as u8);
+            let properties = vec![
+                ("element", Property::Int(elem.id.into())),
+                ("cell_x", Property::Int(sprite.cell_x.into())),
+                ("cell_y", Property::Int(sprite.cell_y.into())),
+                ("cell_z", Property::Int(sprite.cell_z.into())),
+                ("height", Property::Int(sprite.height.into())),
+                ("group_key", Property::Int(sprite.group_key.into())),
+                ("group_id", Property::Int(sprite.group_id.into())),
+                ("z_order", Property::Int(*z_order as i64)),
+                ("tint", Property::Color(color)),
+            ];
+            layers.entry(sprite.layer).or_default().push(Object {
+                id: 0,
+                gid,
+                position: to_tiled(sprite.anchor_position(elem), min),
+                width: tile.width,
+                height: tile.height,
+                properties,
+            });
+        }
+
+        // ids follow the draw order, layer by layer
+        let mut layers = layers.into_iter().collect_vec();
+        for (id, object) in layers
+            .iter_mut()
+            .flat_map(|(_, objects)| objects)
+            .enumerate()
+        {
+            object.id = id as u32 + 1;
+        }
+
+        Self {
+            width: max.x - min.x + 1,
+            height: max.y - min.y + 1,
+            tiles,
+            layers,
+        }
+    }
+
+    /// Writes the map as TMX when `out` has a `tmx` extension and as JSON otherwise,
+    /// along with the tile images in a `tiles` directory next to it.
+    /// Returns the number of tiles written.
+    pub fn save(
+        &self,
+        library: &ElementLibrary,
+        textures: &mut Textures,
+        out: &Path,
+    ) -> Result<usize> {
+        let dir = out.parent().unwrap_or_else(|| Path::new(""));
+        fs::create_dir_all(dir.join("tiles"))?;
+
+        let mut written = 0;
+        for tile in &self.tiles {
+            let elem = match library.get(tile.element) {
+                Some(elem) => elem,
+                None => continue,
+            };
+            let rect = match &elem.animation {
+                Some(frames) => frames
+                    .frame_rects
+                    .get(tile.frame)
+                    .copied()
+                    .unwrap_or_else(|| elem.rect()),
+                None => elem.rect(),
+            };
+            match textures.get(elem.texture_id) {
+                Some(texture) => {
+                    let (width, height, pixels) = texture.crop(rect, elem.image_size());
+                    write_png(dir.join(tile.image()), width, height, &pixels)?;
+                    written += 1;
+                }
+                None => eprintln!("missing texture {} of element {}", elem.texture_id, elem.id),
+            }
+        }
+
+        if out.extension() == Some("tmx".as_ref()) {
+            fs::write(out, self.to_tmx())?;
+        } else {
+            fs::write(out, serde_json::to_string_pretty(&self.to_json())?)?;
+        }
+        Ok(written)
+    }
+
+    // the tileset is a collection of images, tiles are as large as the largest one
+    fn tile_size(&self) -> (u32, u32) {
+        let width = self.tiles.iter().map(|tile| tile.width).max();
+        let height = self.tiles.iter().map(|tile| tile.height).max();
+        (width.unwrap_or(0), height.unwrap_or(0))
+    }
+
+    #[inline]
+    fn object_count(&self) -> usize {
+        self.layers.iter().map(|(_, objects)| objects.len()).sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let mut value = json!({
+                    "id": tile.id,
+                    "image": tile.image(),
+                    "imagewidth": tile.width,
+                    "imageheight": tile.height,
+                    "properties": [
+                        { "name": "element", "type": "int", "value": tile.element },
+                        { "name": "frame", "type": "int", "value": tile.frame },
+                    ],
+                });
+                if !tile.animation.is_empty() {
+                    let frames = tile
+                        .animation
+                        .iter()
+                        .map(|(id, duration)| json!({ "tileid": id, "duration": duration }))
+                        .collect_vec();
+                    value["animation"] = json!(frames);
+                }
+                value
+            })
+            .collect_vec();
+        let (tile_width, tile_height) = self.tile_size();
+        let tileset = json!({
+            "firstgid": 1,
+            "name": "elements",
+            "tilewidth": tile_width,
+            "tileheight": tile_height,
+            "tilecount": self.tiles.len(),
+            "columns": 0,
+            "margin": 0,
+            "spacing": 0,
+            "objectalignment": "topleft",
+            "grid": { "orientation": "orthogonal", "width": 1, "height": 1 },
+            "tiles": tiles,
+        });
+
+        let layers = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(idx, (layer, objects))| {
+                let objects = objects
+                    .iter()
+                    .map(|object| {
+                        let properties = object
+                            .properties
+                            .iter()
+                            .map(|(name, prop)| {
+                                json!({ "name": name, "type": prop.kind(), "value": prop.value() })
+                            })
+                            .collect_vec();
+                        json!({
+                            "id": object.id,
+                            "gid": object.gid,
+                            "name": "",
+                            "type": "",
+                            "x": object.position.x,
+                            "y": object.position.y,
+                            "width": object.width,
+                            "height": object.height,
+                            "rotation": 0,
+                            "visible": true,
+                            "properties": properties,
+                        })
+                    })
+                    .collect_vec();
+                json!({
+                    "id": idx + 1,
+                    "name": format!("layer {}", layer),
+                    "type": "objectgroup",
+                    "draworder": "index",
+                    "opacity": 1,
+                    "visible": true,
+                    "x": 0,
+                    "y": 0,
+                    "objects": objects,
+                    "properties": [{ "name": "layer", "type": "int", "value": layer }],
+                })
+            })
+            .collect_vec();
+
+        json!({
+            "type": "map",
+            "version": TILED_VERSION,
+            "orientation": "isometric",
+            "renderorder": "right-down",
+            "width": self.width,
+            "height": self.height,
+            "tilewidth": CELL_WIDTH as u32,
+            "tileheight": CELL_HEIGHT as u32,
+            "infinite": false,
+            "compressionlevel": -1,
+            "nextlayerid": self.layers.len() + 1,
+            "nextobjectid": self.object_count() + 1,
+            "tilesets": [tileset],
+            "layers": layers,
+        })
+    }
+
+    pub fn to_tmx(&self) -> String {
+        let mut xml = String::new();
+        let (tile_width, tile_height) = self.tile_size();
+        // writing to a string can't fail
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<map version="{}" orientation="isometric" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="{}" nextobjectid="{}">"#,
+            TILED_VERSION,
+            self.width,
+            self.height,
+            CELL_WIDTH as u32,
+            CELL_HEIGHT as u32,
+            self.layers.len() + 1,
+            self.object_count() + 1
+        );
+        let _ = writeln!(
+            xml,
+            r#" <tileset firstgid="1" name="elements" tilewidth="{}" tileheight="{}" tilecount="{}" columns="0" objectalignment="topleft">"#,
+            tile_width,
+            tile_height,
+            self.tiles.len()
+        );
+        let _ = writeln!(
+            xml,
+            r#"  <grid orientation="orthogonal" width="1" height="1"/>"#
+        );
+        for tile in &self.tiles {
+            let _ = writeln!(xml, r#"  <tile id="{}">"#, tile.id);
+            let _ = writeln!(xml, "   <properties>");
+            let _ = writeln!(
+                xml,
+                r#"    <property name="element" type="int" value="{}"/>"#,
+                tile.element
+            );
+            let _ = writeln!(
+                xml,
+                r#"    <property name="frame" type="int" value="{}"/>"#,
+                tile.frame
+            );
+            let _ = writeln!(xml, "   </properties>");
+            let _ = writeln!(
+                xml,
+                r#"   <image width="{}" height="{}" source="{}"/>"#,
+                tile.width,
+                tile.height,
+                tile.image()
+            );
+            if !tile.animation.is_empty() {
+                let _ = writeln!(xml, "   <animation>");
+                for (id, duration) in &tile.animation {
+                    let _ = writeln!(
+                        xml,
+                        r#"    <frame tileid="{}" duration="{}"/>"#,
+                        id, duration
+                    );
+                }
+                let _ = writeln!(xml, "   </animation>");
+            }
+            let _ = writeln!(xml, "  </tile>");
+        }
+        let _ = writeln!(xml, " </tileset>");
+
+        for (idx, (layer, objects)) in self.layers.iter().enumerate() {
+            let _ = writeln!(
+                xml,
+                r#" <objectgroup id="{}" name="layer {}" draworder="index">"#,
+                idx + 1,
+                layer
+            );
+            let _ = writeln!(xml, "  <properties>");
+            let _ = writeln!(
+                xml,
+                r#"   <property name="layer" type="int" value="{}"/>"#,
+                layer
+            );
+            let _ = writeln!(xml, "  </properties>");
+            for object in objects {
+                let _ = writeln!(
+                    xml,
+                    r#"  <object id="{}" gid="{}" x="{}" y="{}" width="{}" height="{}">"#,
+                    object.id,
+                    object.gid,
+                    object.position.x,
+                    object.position.y,
+                    object.width,
+                    object.height
+                );
+                let _ = writeln!(xml, "   <properties>");
+                for (name, prop) in &object.properties {
+                    let _ = writeln!(
+                        xml,
+                        r#"    <property name="{}" type="{}" value="{}"/>"#,
+                        name,
+                        prop.kind(),
+                        prop
+                    );
+                }
+                let _ = writeln!(xml, "   </properties>");
+                let _ = writeln!(xml, "  </object>");
+            }
+            let _ = writeln!(xml, " </objectgroup>");
+        }
+        let _ = writeln!(xml, "</map>");
+        xml
+    }
+}
+
+/// Converts a screen position to the pixel coordinates of objects in an isometric Tiled map,
+/// which run along the axes of the grid in units of the tile height. The top corner of the
+/// `min` cell is the origin.
+fn to_tiled(pos: Vec2, min: IVec2) -> Vec2 {
+    let x = pos.x - (min.x - min.y) as f32 * CELL_WIDTH / 2.;
+    // the y axis points down in Tiled
+    let y = -pos.y - (min.x + min.y - 1) as f32 * CELL_HEIGHT / 2.;
+    let ratio = CELL_HEIGHT / CELL_WIDTH;
+    Vec2::new(y + x * ratio, y - x * ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use vakfu_format::map::chunk::MapChunk;
+    use vakfu_format::map::element::{ElementFlags, MapElement};
+    use vakfu_format::map::frames::Frames;
+    use vakfu_format::map::sprite::MapSprite;
+    use vakfu_format::map::version::FormatVersion;
+    use vakfu_format::rect::Rect;
+
+    use super::*;
+
+    fn element(id: i32, flip: bool, animation: Option<Frames>) -> MapElement {
+        MapElement {
+            id,
+            origin_x: 10,
+            origin_y: 20,
+            img_width: 64,
+            img_height: 32,
+            texture_id: id,
+            flags: ElementFlags::new().with_is_flip(flip),
+            visual_height: 0,
+            visibility_mask: 0,
+            export_mask: 0,
+            shader: 0,
+            animation,
+            ground_sound: 0,
+        }
+    }
+
+    fn sprite(cell: (i32, i32), layer: u8, element_id: i32) -> MapSprite {
+        MapSprite {
+            cell_x: cell.0,
+            cell_y: cell.1,
+            layer,
+            element_id,
+            ..Default::default()
+        }
+    }
+
+    fn tiled_map() -> TiledMap {
+        let frames = Frames::new(
+            300,
+            &[100, 200],
+            vec![
+                Rect {
+                    min: Vec2::ZERO,
+                    max: Vec2::new(16., 8.),
+                },
+                Rect {
+                    min: Vec2::new(16., 0.),
+                    max: Vec2::new(32., 8.),
+                },
+            ],
+        );
+        let library = ElementLibrary::new(
+            FormatVersion::Compact,
+            vec![
+                element(1, false, None),
+                element(2, true, None),
+                element(3, false, Some(frames)),
+            ],
+        );
+        let chunk = MapChunk {
+            map_x: 0,
+            map_y: 0,
+            min_x: 0,
+            min_y: 0,
+            min_z: 0,
+            max_x: 1,
+            max_y: 1,
+            max_z: 0,
+            rects: vec![],
+            sprites: vec![
+                sprite((0, 0), 0, 1),
+                sprite((1, 0), 0, 2),
+                sprite((0, 1), 2, 3),
+                sprite((1, 1), 2, 1),
+                // not in the library
+                sprite((1, 1), 2, 99),
+            ],
+        };
+        let map = Map::new(FormatVersion::Compact, vec![("0_0".to_owned(), chunk)]);
+        TiledMap::new(&map, &library)
+    }
+
+    fn assert_keys(value: &Value, keys: &[&str]) {
+        for key in keys {
+            assert!(value.get(key).is_some(), "missing {} in {}", key, value);
+        }
+    }
+
+    /// Checks the required keys of the Tiled JSON map format and that ids and gids resolve.
+    #[test]
+    fn json_format() {
+        let json = tiled_map().to_json();
+        #[rustfmt::skip]
+        assert_keys(&json, &[
+            "type", "version", "orientation", "renderorder", "width", "height", "tilewidth",
+            "tileheight", "infinite", "nextlayerid", "nextobjectid", "layers", "tilesets",
+        ]);
+        assert_eq!(json["type"], "map");
+        assert_eq!(json["orientation"], "isometric");
+
+        let tilesets = json["tilesets"].as_array().unwrap();
+        assert_eq!(tilesets.len(), 1);
+        let tileset = &tilesets[0];
+        #[rustfmt::skip]
+        assert_keys(tileset, &[
+            "firstgid", "name", "tilewidth", "tileheight", "tilecount", "columns", "margin",
+            "spacing", "tiles",
+        ]);
+        let first_gid = tileset["firstgid"].as_u64().unwrap() as u32;
+        let tiles = tileset["tiles"].as_array().unwrap();
+        assert_eq!(tileset["tilecount"], tiles.len());
+        // three elements, one of them with two frames
+        assert_eq!(tiles.len(), 4);
+        let tile_ids: HashSet<u64> = tiles
+            .iter()
+            .map(|tile| {
+                assert_keys(tile, &["id", "image", "imagewidth", "imageheight"]);
+                tile["id"].as_u64().unwrap()
+            })
+            .collect();
+        assert_eq!(tile_ids.len(), tiles.len());
+        let frames: Vec<&Value> = tiles
+            .iter()
+            .filter_map(|tile| tile["animation"].as_array())
+            .flatten()
+            .collect();
+        assert_eq!(frames.len(), 2);
+        for frame in frames {
+            assert_keys(frame, &["tileid", "duration"]);
+            assert!(tile_ids.contains(&frame["tileid"].as_u64().unwrap()));
+        }
+
+        let layers = json["layers"].as_array().unwrap();
+        assert_eq!(layers.len(), 2);
+        let mut layer_ids = HashSet::new();
+        let mut object_ids = HashSet::new();
+        let mut flipped = 0;
+        for layer in layers {
+            #[rustfmt::skip]
+            assert_keys(layer, &[
+                "id", "name", "type", "objects", "draworder", "opacity", "visible", "x", "y",
+            ]);
+            assert_eq!(layer["type"], "objectgroup");
+            assert!(layer_ids.insert(layer["id"].as_u64().unwrap()));
+            for object in layer["objects"].as_array().unwrap() {
+                #[rustfmt::skip]
+                assert_keys(object, &[
+                    "id", "gid", "name", "type", "x", "y", "width", "height", "rotation",
+                    "visible",
+                ]);
+                assert!(object_ids.insert(object["id"].as_u64().unwrap()));
+                let gid = object["gid"].as_u64().unwrap() as u32;
+                if gid & FLIPPED_HORIZONTALLY != 0 {
+                    flipped += 1;
+                }
+                let tile_id = (gid & !FLIPPED_HORIZONTALLY) - first_gid;
+                assert!(tile_ids.contains(&(tile_id as u64)), "gid {}", gid);
+            }
+        }
+        assert_eq!(object_ids.len(), 4);
+        assert_eq!(flipped, 1);
+        assert_eq!(json["nextobjectid"], object_ids.iter().max().unwrap() + 1);
+        assert_eq!(json["nextlayerid"], layer_ids.iter().max().unwrap() + 1);
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use glam::{IVec2, Vec2};
+use itertools::iproduct;
+use serde_json::json;
+use vakfu_format::map::{iso_to_screen, Map};
+use vakfu_format::rect::Rect;
+
+use super::{write_png, Canvas};
+
+/// Screen-space rectangle covered by the cell extents of every chunk of the map.
+pub fn map_bounds(map: &Map) -> Option<Rect> {
+    map.chunks()
+        .iter()
+        .flat_map(|chunk| {
+            iproduct!(
+                [chunk.min_x, chunk.max_x],
+                [chunk.min_y, chunk.max_y],
+                [chunk.min_z, chunk.max_z]
+            )
+        })
+        .map(|(x, y, z)| iso_to_screen(IVec2::new(x, y), z.into()))
+        .fold(None, |acc: Option<Rect>, pos| {
+            let rect = match acc {
+                None => Rect { min: pos, max: pos },
+                Some(rect) => Rect {
+                    min: rect.min.min(pos),
+                    max: rect.max.max(pos),
+                },
+            };
+            Some(rect)
+        })
+}
+
+/// Pixel grid of the deepest zoom level, covering the map bounds at the given scale.
+#[derive(Debug, Clone)]
+pub struct TileGrid {
+    origin: Vec2,
+    scale: f32,
+    width: u32,
+    height: u32,
+    tile_size: u32,
+}
+
+impl TileGrid {
+    pub fn new(bounds: Rect, scale: f32, tile_size: u32) -> Result<Self> {
+        // strips of tiles are halved into the strips of the level below
+        if tile_size == 0 || tile_size % 2 == 1 {
+            return Err(anyhow!("Tile size must be even and not zero"));
+        }
+        let size = (bounds.size() * scale).ceil();
+        let grid = TileGrid {
+            origin: Vec2::new(bounds.min.x, bounds.max.y),
+            scale,
+            width: size.x.max(1.) as u32,
+            height: size.y.max(1.) as u32,
+            tile_size,
+        };
+        Ok(grid)
+    }
+
+    /// Lowest zoom level at which the grid is displayed at full resolution,
+    /// given that the whole grid fits in a single tile at zoom 0.
+    pub fn max_zoom(&self) -> u32 {
+        let mut zoom = 0;
+        while self.width.max(self.height) > self.tile_size << zoom {
+            zoom += 1;
+        }
+        zoom
+    }
+
+    #[inline]
+    fn columns(&self) -> u32 {
+        self.width.div_ceil(self.tile_size)
+    }
+
+    #[inline]
+    fn rows(&self) -> u32 {
+        self.height.div_ceil(self.tile_size)
+    }
+
+    /// Empty canvas of a row of tiles of the deepest zoom level.
+    fn strip(&self, row: u32) -> Canvas {
+        let top = (row * self.tile_size) as f32 / self.scale;
+        Canvas::with_size(
+            self.origin - Vec2::new(0., top),
+            self.scale,
+            self.columns() * self.tile_size,
+            self.tile_size,
+        )
+    }
+
+    /// Converts a screen position into pixel coordinates of the deepest zoom level.
+    #[inline]
+    fn to_pixel(&self, pos: Vec2) -> Vec2 {
+        Vec2::new(pos.x - self.origin.x, self.origin.y - pos.y) * self.scale
+    }
+}
+
+/// Writes a `z/x/y.png` pyramid along with a `tiles.json` manifest. The deepest zoom level is
+/// rendered a row of tiles at a time with `render`, each level below halves the resolution
+/// of the one above it. Tiles without any opaque pixel are skipped.
+/// Returns the number of tiles written.
+pub fn export_tiles(
+    grid: &TileGrid,
+    out: &Path,
+    mut render: impl FnMut(Canvas) -> Canvas,
+) -> Result<usize> {
+    let max_zoom = grid.max_zoom();
+    write_manifest(grid, max_zoom, out)?;
+
+    let mut pyramid = Pyramid {
+        out,
+        tile_size: grid.tile_size,
+        pending: (0..=max_zoom).map(|_| None).collect(),
+        written: 0,
+    };
+    for row in 0..grid.rows() {
+        pyramid.add(max_zoom, row, render(grid.strip(row)))?;
+    }
+    pyramid.finish()?;
+    Ok(pyramid.written)
+}
+
+/// Levels of tiles built from rows of tiles as they come in, so that only a row per level is
+/// kept in memory. Pairs of rows are halved into a row of the level below.
+struct Pyramid<'a> {
+    out: &'a Path,
+    tile_size: u32,
+    // the even row of each level waiting for the row below it, already halved
+    pending: Vec<Option<(u32, Canvas)>>,
+    written: usize,
+}
+
+impl<'a> Pyramid<'a> {
+    fn add(&mut self, zoom: u32, row: u32, strip: Canvas) -> Result<()> {
+        self.write_row(zoom, row, &strip)?;
+        if zoom == 0 {
+            return Ok(());
+        }
+        let half = strip.downsample();
+        match self.pending[zoom as usize].take() {
+            Some((_, mut above)) if row % 2 == 1 => {
+                above.append(&half);
+                self.add(zoom - 1, row / 2, above)
+            }
+            _ => {
+                self.pending[zoom as usize] = Some((row, half));
+                Ok(())
+            }
+        }
+    }
+
+    /// Halves the rows left without a row below them, the deepest levels first.
+    fn finish(&mut self) -> Result<()> {
+        for zoom in (1..self.pending.len() as u32).rev() {
+            if let Some((row, mut above)) = self.pending[zoom as usize].take() {
+                let (origin, scale, width) = (above.origin(), above.scale(), above.width());
+                let below = Canvas::with_size(origin, scale, width, above.height());
+                above.append(&below);
+                self.add(zoom - 1, row / 2, above)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, zoom: u32, row: u32, strip: &Canvas) -> Result<()> {
+        let tile_size = self.tile_size;
+        for x in 0..strip.width().div_ceil(tile_size) {
+            let pixels = strip.region(x * tile_size, 0, tile_size, tile_size);
+            if pixels.chunks_exact(4).all(|px| px[3] == 0) {
+                continue;
+            }
+            let dir = self.out.join(zoom.to_string()).join(x.to_string());
+            fs::create_dir_all(&dir)?;
+            write_png(
+                dir.join(format!("{}.png", row)),
+                tile_size,
+                tile_size,
+                &pixels,
+            )?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+fn write_manifest(grid: &TileGrid, max_zoom: u32, out: &Path) -> Result<()> {
+    // cell coordinates map linearly to screen space, derive the transform from the projection
+    let origin = iso_to_screen(IVec2::ZERO, 0);
+    let dx = iso_to_screen(IVec2::X, 0) - origin;
+    let dy = iso_to_screen(IVec2::Y, 0) - origin;
+    let dz = iso_to_screen(IVec2::ZERO, 1) - origin;
+    let to_pixel = |v: Vec2| grid.to_pixel(v) - grid.to_pixel(Vec2::ZERO);
+    let (dx, dy, dz) = (to_pixel(dx), to_pixel(dy), to_pixel(dz));
+    let offset = grid.to_pixel(origin);
+
+    let top_left = grid.origin;
+    let size = Vec2::new(grid.width as f32, -(grid.height as f32)) / grid.scale;
+    let bottom_right = top_left + size;
+    let manifest = json!({
+        "tile_size": grid.tile_size,
+        "min_zoom": 0,
+        "max_zoom": max_zoom,
+        "width": grid.width,
+        "height": grid.height,
+        "bounds": {
+            "min": [top_left.x, bottom_right.y],
+            "max": [bottom_right.x, top_left.y],
+        },
+        // pixel = row . [cell_x, cell_y, cell_z, 1] at max_zoom, halved for each level below
+        "cell_to_pixel": [
+            [dx.x, dy.x, dz.x, offset.x],
+            [dx.y, dy.y, dz.y, offset.y],
+        ],
+    });
+
+    fs::create_dir_all(out)?;
+    fs::write(
+        out.join("tiles.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    use serde_json::Value;
+    use vakfu_format::map::chunk::MapChunk;
+    use vakfu_format::map::version::FormatVersion;
+
+    use super::*;
+
+    fn chunk(min: (i32, i32, i16), max: (i32, i32, i16)) -> MapChunk {
+        MapChunk {
+            map_x: min.0,
+            map_y: min.1,
+            min_x: min.0,
+            min_y: min.1,
+            min_z: min.2,
+            max_x: max.0,
+            max_y: max.1,
+            max_z: max.2,
+            rects: vec![],
+            sprites: vec![],
+        }
+    }
+
+    fn rect(min: (f32, f32), max: (f32, f32)) -> Rect {
+        Rect {
+            min: Vec2::new(min.0, min.1),
+            max: Vec2::new(max.0, max.1),
+        }
+    }
+
+    fn out_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Paths of the tiles written under `out`, as `z/x/y`.
+    fn tiles(out: &Path) -> BTreeSet<String> {
+        let mut tiles = BTreeSet::new();
+        for zoom in fs::read_dir(out).unwrap() {
+            let zoom = zoom.unwrap().path();
+            for x in fs::read_dir(&zoom).into_iter().flatten() {
+                for y in fs::read_dir(x.unwrap().path()).unwrap() {
+                    let path = y.unwrap().path();
+                    let relative = path.strip_prefix(out).unwrap().with_extension("");
+                    tiles.insert(relative.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn bounds() {
+        let map = Map::new(
+            FormatVersion::Compact,
+            vec![
+                ("0_0".to_owned(), chunk((0, 0, 0), (2, 2, 0))),
+                ("4_0".to_owned(), chunk((4, 0, -1), (6, 0, 2))),
+            ],
+        );
+        let bounds = map_bounds(&map).unwrap();
+        assert_eq!(bounds.min, Vec2::new(-86., -139.));
+        assert_eq!(bounds.max, Vec2::new(258., 0.));
+
+        let empty = Map::new(FormatVersion::Compact, vec![]);
+        assert!(map_bounds(&empty).is_none());
+    }
+
+    #[test]
+    fn zoom_levels() {
+        let max_zoom = |width, tile_size| {
+            let bounds = rect((0., -10.), (width, 0.));
+            TileGrid::new(bounds, 1., tile_size).unwrap().max_zoom()
+        };
+        assert_eq!(max_zoom(256., 256), 0);
+        assert_eq!(max_zoom(257., 256), 1);
+        assert_eq!(max_zoom(1000., 256), 2);
+        assert_eq!(max_zoom(1025., 256), 3);
+        assert_eq!(max_zoom(1025., 512), 2);
+
+        let bounds = rect((0., -10.), (10., 0.));
+        assert!(TileGrid::new(bounds, 1., 0).is_err());
+        assert!(TileGrid::new(bounds, 1., 255).is_err());
+    }
+
+    #[test]
+    fn skips_empty_tiles() {
+        let out = out_dir("vakfu-tiles-export");
+        let grid = TileGrid::new(rect((0., -600.), (1000., 0.)), 1., 256).unwrap();
+        // a single opaque pixel in two of the rows of tiles
+        let points = [Vec2::new(700.5, -300.5), Vec2::new(100.5, -550.5)];
+        let mut strips = 0;
+        let written = export_tiles(&grid, &out, |mut canvas| {
+            strips += 1;
+            for point in points {
+                let pixel = canvas.to_pixel(point).floor();
+                if pixel.y >= 0. && pixel.y < canvas.height() as f32 {
+                    let idx = (pixel.y as usize * canvas.width() as usize + pixel.x as usize) * 4;
+                    canvas.pixels[idx..idx + 4].copy_from_slice(&[255; 4]);
+                }
+            }
+            canvas
+        })
+        .unwrap();
+
+        assert_eq!(strips, 3);
+        assert_eq!(written, 5);
+        let expected = ["0/0/0", "1/0/1", "1/1/0", "2/0/2", "2/2/1"];
+        assert_eq!(
+            tiles(&out),
+            expected.iter().map(|s| s.to_string()).collect()
+        );
+
+        // a level below, the pixel is averaged with three transparent ones
+        let decoder = png::Decoder::new(fs::File::open(out.join("1/0/1.png")).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        let idx = ((275 - 256) * 256 + 50) * 4;
+        assert_eq!(pixels[idx..idx + 4], [255, 255, 255, 63]);
+    }
+
+    #[test]
+    fn manifest() {
+        let out = out_dir("vakfu-tiles-manifest");
+        let bounds = rect((-300., -200.), (500., 100.));
+        let scale = 2.;
+        let grid = TileGrid::new(bounds, scale, 256).unwrap();
+        export_tiles(&grid, &out, |canvas| canvas).unwrap();
+        assert!(tiles(&out).is_empty());
+
+        let manifest: Value =
+            serde_json::from_slice(&fs::read(out.join("tiles.json")).unwrap()).unwrap();
+        assert_eq!(manifest["max_zoom"], 3);
+        assert_eq!(
+            (manifest["width"].as_u64(), manifest["height"].as_u64()),
+            (Some(1600), Some(600))
+        );
+
+        let rows = manifest["cell_to_pixel"].as_array().unwrap();
+        let apply = |row: &Value, cell: [f32; 3]| {
+            let row: Vec<f32> = serde_json::from_value(row.clone()).unwrap();
+            row[0] * cell[0] + row[1] * cell[1] + row[2] * cell[2] + row[3]
+        };
+        for (x, y, z) in [(0, 0, 0), (3, 2, 1), (-1, 4, -2), (7, -5, 3)] {
+            let screen = iso_to_screen(IVec2::new(x, y), z);
+            let expected = Vec2::new(screen.x - bounds.min.x, bounds.max.y - screen.y) * scale;
+            let cell = [x as f32, y as f32, z as f32];
+            let pixel = Vec2::new(apply(&rows[0], cell), apply(&rows[1], cell));
+            assert!(
+                (pixel - expected).abs().max_element() < 1e-3,
+                "{} != {}",
+                pixel,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use bevy::ecs::system::lifetimeless::SRes;
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::{Indices, MeshVertexBufferLayout, PrimitiveTopology};
+use bevy::render::render_asset::{PrepareAssetError, RenderAsset, RenderAssets};
+use bevy::render::render_resource::*;
+use bevy::render::renderer::RenderDevice;
+use bevy::sprite::{Material2dPipeline, Material2dPlugin, Mesh2dHandle, SpecializedMaterial2d};
+use vakfu_format::map::element::{BlendMode, MapElement};
+
+use super::render::{Animation, SpriteProperties, SpriteSource, VisibilityFlags};
+
+const BLEND_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5a1c_3e7d_9b02_f846);
+
+/// Renders the sprites of elements that aren't alpha blended, the sprite pipeline
+/// only does alpha blending so they are drawn as quads with a material per blend mode.
+pub struct BlendPlugin;
+
+impl Plugin for BlendPlugin {
+    fn build(&self, app: &mut App) {
+        let shader = Shader::from_wgsl(include_str!("blend.wgsl"));
+        app.world
+            .resource_mut::<Assets<Shader>>()
+            .set_untracked(BLEND_SHADER_HANDLE, shader);
+        app.add_plugin(Material2dPlugin::<BlendMaterial>::default());
+    }
+}
+
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "8f0b6a2e-61d4-4c3a-9a57-2d3f1e0c7b94"]
+pub struct BlendMaterial {
+    pub texture: Handle<Image>,
+    pub color: Color,
+    pub mode: BlendMode,
+}
+
+pub struct GpuBlendMaterial {
+    bind_group: BindGroup,
+    mode: BlendMode,
+}
+
+impl RenderAsset for BlendMaterial {
+    type ExtractedAsset = BlendMaterial;
+    type PreparedAsset = GpuBlendMaterial;
+    type Param = (
+        SRes<RenderDevice>,
+        SRes<Material2dPipeline<BlendMaterial>>,
+        SRes<RenderAssets<Image>>,
+    );
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        material: Self::ExtractedAsset,
+        (render_device, pipeline, gpu_images): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let texture = Some(material.texture.clone());
+        let (texture_view, sampler) = match pipeline
+            .mesh2d_pipeline
+            .get_image_texture(gpu_images, &texture)
+        {
+            Some(result) => result,
+            None => return Err(PrepareAssetError::RetryNextUpdate(material)),
+        };
+
+        let color: Vec<u8> = material
+            .color
+            .as_linear_rgba_f32()
+            .iter()
+            .flat_map(|component| component.to_le_bytes())
+            .collect();
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("blend_material_uniform_buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            contents: &color,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("blend_material_bind_group"),
+            layout: &pipeline.material2d_layout,
+        });
+
+        Ok(GpuBlendMaterial {
+            bind_group,
+            mode: material.mode,
+        })
+    }
+}
+
+impl SpecializedMaterial2d for BlendMaterial {
+    type Key = BlendMode;
+
+    fn key(_render_device: &RenderDevice, material: &GpuBlendMaterial) -> Self::Key {
+        material.mode
+    }
+
+    fn specialize(
+        key: Self::Key,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in &mut fragment.targets {
+                target.blend = Some(blend_state(key));
+            }
+        }
+        Ok(())
+    }
+
+    fn bind_group(material: &GpuBlendMaterial) -> &BindGroup {
+        &material.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(16),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("blend_material_layout"),
+        })
+    }
+
+    fn fragment_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(BLEND_SHADER_HANDLE.typed())
+    }
+}
+
+/// The shader outputs premultiplied colors.
+fn blend_state(mode: BlendMode) -> BlendState {
+    let (src_factor, dst_factor) = match mode {
+        BlendMode::Alpha => (BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+        BlendMode::Additive => (BlendFactor::One, BlendFactor::One),
+        // lerps between the destination and the destination times the color by its alpha
+        BlendMode::Multiply => (BlendFactor::Dst, BlendFactor::OneMinusSrcAlpha),
+    };
+    BlendState {
+        color: BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        },
+        alpha: BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+    }
+}
+
+/// Sprite drawn with a blend material, stands in for `TextureAtlasSprite`.
+#[derive(Debug, Default, Component)]
+pub struct BlendedSprite {
+    pub index: usize,
+    pub flip_x: bool,
+    frames: Vec<Mesh2dHandle>,
+}
+
+impl BlendedSprite {
+    pub fn new(frames: Vec<Mesh2dHandle>, flip_x: bool) -> Self {
+        Self {
+            index: 0,
+            flip_x,
+            frames,
+        }
+    }
+
+    /// Mesh of the current frame.
+    #[inline]
+    pub fn mesh(&self) -> Mesh2dHandle {
+        self.frames[self.index].clone()
+    }
+}
+
+#[derive(Default, Bundle)]
+pub struct BlendedSpriteBundle {
+    pub sprite: BlendedSprite,
+    pub mesh: Mesh2dHandle,
+    pub material: Handle<BlendMaterial>,
+    // unused for rendering, picking tests against its frames
+    pub texture_atlas: Handle<TextureAtlas>,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub computed_visibility: ComputedVisibility,
+    pub properties: SpriteProperties,
+    pub visibility_flags: VisibilityFlags,
+    pub source: SpriteSource,
+}
+
+/// Quads for each frame of an element, anchored at their top-left corner like the sprites.
+pub fn frame_meshes(element: &MapElement, meshes: &mut Assets<Mesh>) -> Vec<Mesh2dHandle> {
+    let rects = element
+        .animation
+        .as_ref()
+        .map(|frames| frames.frame_rects.clone())
+        .unwrap_or_else(|| vec![element.rect()]);
+    let image_size = element.image_size();
+    let flip = element.flags.is_flip();
+
+    rects
+        .iter()
+        .map(|rect| {
+            let size = rect.size();
+            let min = rect.min / image_size;
+            let max = rect.max / image_size;
+            let (left, right) = if flip { (max.x, min.x) } else { (min.x, max.x) };
+
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            let positions = vec![
+                [0., 0., 0.],
+                [0., -size.y, 0.],
+                [size.x, -size.y, 0.],
+                [size.x, 0., 0.],
+            ];
+            let uvs = vec![[left, min.y], [left, max.y], [right, max.y], [right, min.y]];
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; 4]);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+            mesh.set_indices(Some(Indices::U16(vec![0, 1, 2, 0, 2, 3])));
+            Mesh2dHandle(meshes.add(mesh))
+        })
+        .collect()
+}
+
+pub fn blend_animation_system(
+    time: Res<Time>,
+    mut query: Query<(
+        &Animation,
+        &mut BlendedSprite,
+        &mut Mesh2dHandle,
+        &Visibility,
+    )>,
+) {
+    let ms = time.time_since_startup().as_millis() as u64;
+    for (anim, mut sprite, mut mesh, visibility) in query.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+        let index = anim.frame(ms);
+        if sprite.index != index {
+            sprite.index = index;
+            *mesh = sprite.mesh();
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use vakfu_format::map::source::MapSource;
+
+use super::path::PathTool;
+use super::render::MapChunkView;
+use super::stream::ChunkStream;
+use crate::commands::GamePaths;
+
+/// Maps found under the game path, along with the one being viewed.
+pub struct MapBrowser {
+    paths: GamePaths,
+    maps: Vec<i32>,
+    current: i32,
+    requested: Option<i32>,
+    // why the last requested map couldn't be opened
+    error: Option<String>,
+}
+
+impl MapBrowser {
+    pub fn new(paths: GamePaths, maps: Vec<i32>, current: i32) -> Self {
+        Self {
+            paths,
+            maps,
+            current,
+            requested: None,
+            error: None,
+        }
+    }
+
+    #[inline]
+    pub fn paths(&self) -> &GamePaths {
+        &self.paths
+    }
+
+    #[inline]
+    pub fn maps(&self) -> &[i32] {
+        &self.maps
+    }
+
+    #[inline]
+    pub fn current(&self) -> i32 {
+        self.current
+    }
+
+    #[inline]
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Switches to another map on the next update.
+    pub fn request(&mut self, id: i32) {
+        if id != self.current {
+            self.requested = Some(id);
+        }
+    }
+}
+
+pub fn map_browser_system(
+    mut commands: Commands,
+    mut browser: ResMut<MapBrowser>,
+    mut stream: ResMut<ChunkStream>,
+    mut path_tool: ResMut<PathTool>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    chunk_views: Query<&MapChunkView>,
+) {
+    let id = match browser.requested.take() {
+        Some(id) => id,
+        None => return,
+    };
+    let source = match MapSource::open(browser.paths.map(id)) {
+        Ok(source) => source,
+        Err(err) => {
+            browser.error = Some(format!("Failed to open map {}: {}", id, err));
+            return;
+        }
+    };
+
+    // the chunks of the new map get spawned by the stream as they come into view
+    stream.replace(source, &mut commands, &chunk_views);
+    browser.current = id;
+    browser.error = None;
+    path_tool.start = None;
+    path_tool.end = None;
+    path_tool.updated = true;
+
+    let mut camera = cameras.single_mut();
+    camera.translation.x = 0.;
+    camera.translation.y = 0.;
+    camera.scale = Vec3::ONE;
+}
@@ -63,3 +63,18 @@ pub fn camera_system(
         cam.scale = (cam.scale * factor).max(Vec3::ONE);
     }
 }
+
+/// Position of the cursor in world coordinates, if it's inside the window.
+pub fn cursor_to_world(window: &Window, camera: &Transform) -> Option<Vec2> {
+    let window_size = Vec2::new(window.width(), window.height());
+    let cursor = window.cursor_position()?;
+    let offset = (cursor - window_size / 2.) * camera.scale.truncate();
+    Some(camera.translation.truncate() + offset)
+}
+
+/// Inverse of `cursor_to_world`, the position is relative to the bottom-left of the window.
+pub fn world_to_window(window: &Window, camera: &Transform, pos: Vec2) -> Vec2 {
+    let window_size = Vec2::new(window.width(), window.height());
+    let offset = (pos - camera.translation.truncate()) / camera.scale.truncate();
+    offset + window_size / 2.
+}
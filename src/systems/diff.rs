@@ -0,0 +1,441 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContext};
+use futures_lite::future;
+use vakfu_format::diff::MapDiff;
+use vakfu_format::jar::Jar;
+use vakfu_format::map::element::ElementLibrary;
+use vakfu_format::map::sprite::MapSprite;
+use vakfu_format::map::Map;
+use vakfu_format::tgam::Tgam;
+
+use super::blend::BlendMaterial;
+use super::browser::MapBrowser;
+use super::render::{Animation, SpriteProperties, SpriteSource};
+use super::settings::Settings;
+use super::stream::{new_atlas, ChunkStream};
+use crate::assets::tgam::tgam_image;
+use crate::commands::GamePaths;
+
+/// How a sprite differs between the old and the new version of a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl SpriteStatus {
+    pub fn tint(self) -> Color {
+        match self {
+            SpriteStatus::Added => Color::rgb(0.3, 1., 0.3),
+            SpriteStatus::Removed => Color::rgb(1., 0.25, 0.25),
+            SpriteStatus::Changed => Color::rgb(1., 1., 0.25),
+        }
+    }
+
+    /// Color of a sprite drawn with `base`, added sprites fade in and removed ones fade out
+    /// as `fade` goes from the old version to the new one.
+    pub fn color(self, base: Color, fade: f32) -> Color {
+        let [r, g, b, a] = base.as_rgba_f32();
+        let [tr, tg, tb, _] = self.tint().as_rgba_f32();
+        let alpha = match self {
+            SpriteStatus::Added => fade,
+            SpriteStatus::Removed => 1. - fade,
+            SpriteStatus::Changed => 1.,
+        };
+        Color::rgba(r * tr, g * tg, b * tb, a * alpha)
+    }
+}
+
+/// A sprite of the old version of the map that is gone from the new one, drawn at the depth
+/// of its old position. Ghosts aren't part of the map and can't be picked.
+#[derive(Debug, Default, Component)]
+pub struct DiffGhost {
+    pub sprite: MapSprite,
+}
+
+/// Position of its chunk and index in the sprites of the chunk, which tells apart stacked
+/// copies of a sprite.
+type SpriteId = ((i32, i32), usize);
+
+/// Old installation the viewed map is compared to, along with its textures and both element
+/// libraries.
+#[derive(Clone)]
+struct Versions {
+    old: GamePaths,
+    old_gfx: Arc<Jar>,
+    old_library: Arc<ElementLibrary>,
+    new_library: Arc<ElementLibrary>,
+}
+
+impl Versions {
+    fn compare(&self, new: &GamePaths, map: i32) -> Result<Comparison> {
+        let old_path = self.old.map(map);
+        if !old_path.exists() {
+            return Err(anyhow!("Map {} is not in the old version", map));
+        }
+        let old_map = Map::load(File::open(old_path)?)?;
+        let new_map = Map::load(File::open(new.map(map))?)?;
+        let diff = MapDiff::new(&old_map, &self.old_library, &new_map, &self.new_library);
+
+        let mut statuses: HashMap<SpriteId, SpriteStatus> = diff
+            .added
+            .iter()
+            .map(|sprite| (sprite.id(), SpriteStatus::Added))
+            .collect();
+        // a sprite can be moved and tinted differently at once
+        let mut changed = 0;
+        let changed_sprites = diff
+            .moved
+            .iter()
+            .chain(&diff.tint_changed)
+            .chain(diff.texture_changed.iter().map(|(pair, _, _)| pair))
+            .map(|pair| pair.new);
+        for sprite in changed_sprites {
+            if let Entry::Vacant(entry) = statuses.entry(sprite.id()) {
+                entry.insert(SpriteStatus::Changed);
+                changed += 1;
+            }
+        }
+
+        let ranks: HashMap<(i32, i32), Vec<usize>> = old_map
+            .chunks()
+            .iter()
+            .map(|chunk| ((chunk.map_x, chunk.map_y), chunk.cell_ranks()))
+            .collect();
+        let removed: Vec<Ghost> = diff
+            .removed
+            .iter()
+            .map(|sprite| Ghost {
+                sprite: sprite.sprite.clone(),
+                rank: ranks
+                    .get(&sprite.chunk)
+                    .and_then(|ranks| ranks.get(sprite.index))
+                    .copied()
+                    .unwrap_or(0),
+            })
+            .collect();
+
+        // removed elements may be gone from the new textures, or drawn from other ones
+        let mut textures = HashMap::new();
+        for ghost in &removed {
+            let elem = match self.old_library.get(ghost.sprite.element_id) {
+                Some(elem) => elem,
+                None => continue,
+            };
+            if let Entry::Vacant(entry) = textures.entry(elem.texture_id) {
+                match self.old_texture(elem.texture_id) {
+                    Ok(image) => {
+                        entry.insert(image);
+                    }
+                    Err(err) => eprintln!("{:#}", err),
+                }
+            }
+        }
+        Ok(Comparison {
+            map,
+            added: diff.added.len(),
+            changed,
+            statuses,
+            removed,
+            textures,
+        })
+    }
+
+    fn old_texture(&self, id: i32) -> Result<Image> {
+        let name = format!("gfx/{}.tgam", id);
+        let bytes = self
+            .old_gfx
+            .read(&name)
+            .with_context(|| format!("reading {} of the old version", name))?;
+        let (tgam, _) = Tgam::parse(&bytes).map_err(|err| err.in_entry(&name))?;
+        Ok(tgam_image(&tgam))
+    }
+}
+
+/// A removed sprite, with its rank among the sprites of its cell in the old version.
+struct Ghost {
+    sprite: MapSprite,
+    rank: usize,
+}
+
+/// Changes of a map from its old version, owned so that it can be computed in the background.
+struct Comparison {
+    map: i32,
+    added: usize,
+    changed: usize,
+    statuses: HashMap<SpriteId, SpriteStatus>,
+    removed: Vec<Ghost>,
+    // textures of the removed sprites decoded from the old version, by texture id
+    textures: HashMap<i32, Image>,
+}
+
+enum ComparisonState {
+    Loading(i32, Task<Result<Comparison>>),
+    Loaded(Comparison),
+    Failed(i32, String),
+}
+
+impl ComparisonState {
+    fn map(&self) -> i32 {
+        match self {
+            ComparisonState::Loading(map, _) => *map,
+            ComparisonState::Loaded(comparison) => comparison.map,
+            ComparisonState::Failed(map, _) => *map,
+        }
+    }
+}
+
+/// Overlays the old version of the viewed map, taken from another installation of the game.
+/// Sprites are tinted by how they changed and the removed ones are drawn as ghosts where they
+/// used to be.
+pub struct MapDiffView {
+    versions: Option<Versions>,
+    pub enabled: bool,
+    /// From 0 showing the old version of the map to 1 showing the new one.
+    pub fade: f32,
+    state: Option<ComparisonState>,
+    // textures and atlases of the removed sprites, by id in the old version
+    textures: HashMap<i32, Handle<Image>>,
+    atlases: HashMap<i32, Handle<TextureAtlas>>,
+    updated: bool,
+}
+
+impl Default for MapDiffView {
+    fn default() -> Self {
+        Self {
+            versions: None,
+            enabled: true,
+            fade: 1.,
+            state: None,
+            textures: HashMap::new(),
+            atlases: HashMap::new(),
+            updated: false,
+        }
+    }
+}
+
+impl MapDiffView {
+    pub fn new(
+        old: GamePaths,
+        old_gfx: Jar,
+        old_library: ElementLibrary,
+        new_library: ElementLibrary,
+    ) -> Self {
+        Self {
+            versions: Some(Versions {
+                old,
+                old_gfx: Arc::new(old_gfx),
+                old_library: Arc::new(old_library),
+                new_library: Arc::new(new_library),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// How a sprite of the viewed map changed, if the changes are shown.
+    pub fn status(&self, source: &SpriteSource) -> Option<SpriteStatus> {
+        match &self.state {
+            Some(ComparisonState::Loaded(comparison)) if self.enabled => comparison
+                .statuses
+                .get(&(source.chunk, source.index))
+                .copied(),
+            _ => None,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn map_diff_system(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut view: ResMut<MapDiffView>,
+    browser: Res<MapBrowser>,
+    stream: Res<ChunkStream>,
+    pool: Res<AsyncComputeTaskPool>,
+    mut images: ResMut<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    ghosts: Query<Entity, With<DiffGhost>>,
+) {
+    let view = &mut *view;
+    let versions = match &view.versions {
+        Some(versions) => versions,
+        None => return,
+    };
+
+    // compare again once another map is viewed
+    let current = browser.current();
+    if view.state.as_ref().map(ComparisonState::map) != Some(current) {
+        for entity in ghosts.iter() {
+            commands.entity(entity).despawn();
+        }
+        let versions = versions.clone();
+        let paths = browser.paths().clone();
+        let task = pool.spawn(async move { versions.compare(&paths, current) });
+        view.state = Some(ComparisonState::Loading(current, task));
+        view.updated = true;
+    }
+
+    if let Some(ComparisonState::Loading(map, task)) = &mut view.state {
+        if let Some(result) = future::block_on(future::poll_once(task)) {
+            view.state = Some(match result {
+                Ok(mut comparison) => {
+                    for (id, image) in comparison.textures.drain() {
+                        view.textures.entry(id).or_insert_with(|| images.add(image));
+                    }
+                    for ghost in &comparison.removed {
+                        let sprite = &ghost.sprite;
+                        let elem = match versions.old_library.get(sprite.element_id) {
+                            Some(elem) => elem,
+                            None => continue,
+                        };
+                        let texture = match view.textures.get(&elem.texture_id) {
+                            Some(texture) => texture,
+                            None => continue,
+                        };
+                        let handle = view.atlases.entry(elem.id).or_insert_with(|| {
+                            let rects = elem
+                                .animation
+                                .as_ref()
+                                .map(|frames| frames.frame_rects.as_slice());
+                            atlases.add(new_atlas(
+                                texture.clone(),
+                                elem.image_size(),
+                                rects.unwrap_or(&[elem.rect()]),
+                            ))
+                        });
+                        let cell = IVec2::new(sprite.cell_x, sprite.cell_y);
+                        let z = stream.source().depth(cell, ghost.rank);
+                        let mut entity = commands.spawn_bundle(SpriteSheetBundle {
+                            sprite: TextureAtlasSprite {
+                                flip_x: elem.flags.is_flip(),
+                                color: SpriteStatus::Removed
+                                    .color(sprite.color().into(), view.fade),
+                                anchor: Anchor::TopLeft,
+                                ..Default::default()
+                            },
+                            texture_atlas: handle.clone(),
+                            transform: Transform::from_translation(
+                                sprite.anchor_position(elem).extend(z),
+                            ),
+                            visibility: Visibility {
+                                is_visible: view.enabled && view.fade < 1.,
+                            },
+                            ..Default::default()
+                        });
+                        entity.insert(DiffGhost {
+                            sprite: sprite.clone(),
+                        });
+                        if let Some(frames) = &elem.animation {
+                            entity.insert(Animation::new(frames));
+                        }
+                    }
+                    ComparisonState::Loaded(comparison)
+                }
+                Err(err) => ComparisonState::Failed(*map, err.to_string()),
+            });
+            view.updated = true;
+        }
+    }
+
+    let copy = (view.enabled, view.fade);
+    egui::Window::new("Diff").show(egui_context.ctx_mut(), |ui| {
+        ui.checkbox(&mut view.enabled, "Show changes from the old version");
+        ui.add(egui::Slider::new(&mut view.fade, 0.0..=1.).text("Old to new"));
+        match &view.state {
+            Some(ComparisonState::Loaded(comparison)) => {
+                let counts = [
+                    (SpriteStatus::Added, comparison.added),
+                    (SpriteStatus::Removed, comparison.removed.len()),
+                    (SpriteStatus::Changed, comparison.changed),
+                ];
+                for (status, count) in counts {
+                    let [r, g, b, _] = status.tint().as_rgba_f32();
+                    let color = egui::Color32::from_rgb(
+                        (r * 255.) as u8,
+                        (g * 255.) as u8,
+                        (b * 255.) as u8,
+                    );
+                    ui.colored_label(color, format!("{} {:?}", count, status));
+                }
+            }
+            Some(ComparisonState::Failed(_, err)) => {
+                ui.label(err);
+            }
+            _ => {
+                ui.label("Comparing...");
+            }
+        }
+    });
+    if (view.enabled, view.fade) != copy {
+        view.updated = true;
+    }
+}
+
+type DiffColor<'a> = (
+    &'a SpriteProperties,
+    &'a SpriteSource,
+    ChangeTrackers<SpriteSource>,
+    Option<&'a mut TextureAtlasSprite>,
+    Option<&'a Handle<BlendMaterial>>,
+);
+
+/// Tints the sprites by how they changed, sprites are colored again when the comparison or
+/// the colors of the settings change and as they get spawned.
+pub fn diff_tint_system(
+    mut view: ResMut<MapDiffView>,
+    settings: Res<Settings>,
+    mut color_by_shader: Local<bool>,
+    mut sprites: Query<DiffColor, Without<DiffGhost>>,
+    mut ghosts: Query<(&DiffGhost, &mut TextureAtlasSprite, &mut Visibility)>,
+    mut materials: ResMut<Assets<BlendMaterial>>,
+) {
+    if view.versions.is_none() {
+        return;
+    }
+    // the settings reset every color when sprites are colored by shader or not
+    let recolor = view.updated || *color_by_shader != settings.color_by_shader;
+    *color_by_shader = settings.color_by_shader;
+
+    for (props, source, tracker, sprite, material) in sprites.iter_mut() {
+        if !recolor && !tracker.is_added() {
+            continue;
+        }
+        let base = settings.sprite_color(&source.sprite, props.shader);
+        let color = match view.status(source) {
+            Some(status) => status.color(base, view.fade),
+            None => base,
+        };
+        if let Some(mut sprite) = sprite {
+            if sprite.color != color {
+                sprite.color = color;
+            }
+        }
+        // changing a material has it prepared again
+        if let Some(handle) = material {
+            let current = materials.get(handle).map(|material| material.color);
+            if current.is_some() && current != Some(color) {
+                if let Some(material) = materials.get_mut(handle) {
+                    material.color = color;
+                }
+            }
+        }
+    }
+
+    if view.updated {
+        for (ghost, mut sprite, mut visibility) in ghosts.iter_mut() {
+            let color = SpriteStatus::Removed.color(ghost.sprite.color().into(), view.fade);
+            sprite.color = color;
+            visibility.is_visible = view.enabled && view.fade < 1.;
+        }
+        view.updated = false;
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use bevy::prelude::*;
+use vakfu_format::map::source::MapSource;
+
+use super::render::SpriteProperties;
+
+/// Filter over the values of one sprite property. Muted values are always hidden, when any
+/// value is soloed only those are shown, otherwise the selected values are shown, or hidden
+/// when inverted. Nothing is filtered out while no value is selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Facet<T> {
+    pub selected: BTreeSet<T>,
+    pub solo: BTreeSet<T>,
+    pub muted: BTreeSet<T>,
+    pub invert: bool,
+}
+
+impl<T> Default for Facet<T> {
+    fn default() -> Self {
+        Self {
+            selected: BTreeSet::new(),
+            solo: BTreeSet::new(),
+            muted: BTreeSet::new(),
+            invert: false,
+        }
+    }
+}
+
+impl<T: Ord> Facet<T> {
+    pub fn passes(&self, value: &T) -> bool {
+        if self.muted.contains(value) {
+            false
+        } else if !self.solo.is_empty() {
+            self.solo.contains(value)
+        } else if self.selected.is_empty() {
+            true
+        } else {
+            self.selected.contains(value) != self.invert
+        }
+    }
+
+    /// Entities of the values that pass one of the filters but not the other.
+    pub fn changed<'a>(
+        &'a self,
+        previous: &'a Facet<T>,
+        entries: &'a BTreeMap<T, HashSet<Entity>>,
+    ) -> impl Iterator<Item = &'a Entity> {
+        entries
+            .iter()
+            .filter(|(value, _)| self.passes(value) != previous.passes(value))
+            .flat_map(|(_, entities)| entities)
+    }
+}
+
+/// Number of sprites of the whole map with each value of the properties they are filtered on.
+#[derive(Debug, Default)]
+pub struct SpriteCounts {
+    pub layers: BTreeMap<u8, usize>,
+    pub group_keys: BTreeMap<i32, usize>,
+    pub group_ids: BTreeMap<i32, usize>,
+}
+
+impl SpriteCounts {
+    /// Decodes every chunk of the map, chunks that fail to decode are skipped since the
+    /// stream reports them as they come into view.
+    pub fn scan(source: &MapSource) -> Self {
+        let mut counts = Self::default();
+        for header in source.chunks() {
+            let chunk = match source.load(header.map_x, header.map_y) {
+                Ok(chunk) => chunk,
+                Err(_) => continue,
+            };
+            for sprite in &chunk.sprites {
+                *counts.layers.entry(sprite.layer).or_default() += 1;
+                *counts.group_keys.entry(sprite.group_key).or_default() += 1;
+                *counts.group_ids.entry(sprite.group_id).or_default() += 1;
+            }
+        }
+        counts
+    }
+}
+
+/// Loaded sprites by the values of the properties they are filtered on, to update the
+/// entities when the filters change.
+#[derive(Debug, Default)]
+pub struct SpriteIndex {
+    pub layers: BTreeMap<u8, HashSet<Entity>>,
+    pub group_keys: BTreeMap<i32, HashSet<Entity>>,
+    pub group_ids: BTreeMap<i32, HashSet<Entity>>,
+}
+
+impl SpriteIndex {
+    pub fn insert(&mut self, entity: Entity, props: &SpriteProperties) {
+        self.layers.entry(props.layer).or_default().insert(entity);
+        self.group_keys
+            .entry(props.group_key)
+            .or_default()
+            .insert(entity);
+        self.group_ids
+            .entry(props.group_id)
+            .or_default()
+            .insert(entity);
+    }
+
+    pub fn remove(&mut self, entity: Entity, props: &SpriteProperties) {
+        remove_entry(&mut self.layers, props.layer, entity);
+        remove_entry(&mut self.group_keys, props.group_key, entity);
+        remove_entry(&mut self.group_ids, props.group_id, entity);
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+        self.group_keys.clear();
+        self.group_ids.clear();
+    }
+}
+
+// values without any loaded sprite are dropped so that they aren't listed
+fn remove_entry<T: Ord>(entries: &mut BTreeMap<T, HashSet<Entity>>, value: T, entity: Entity) {
+    if let Some(entities) = entries.get_mut(&value) {
+        entities.remove(&entity);
+        if entities.is_empty() {
+            entries.remove(&value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facet(selected: &[u8], solo: &[u8], muted: &[u8], invert: bool) -> Facet<u8> {
+        Facet {
+            selected: selected.iter().copied().collect(),
+            solo: solo.iter().copied().collect(),
+            muted: muted.iter().copied().collect(),
+            invert,
+        }
+    }
+
+    fn passing(facet: &Facet<u8>) -> Vec<u8> {
+        (0..5).filter(|value| facet.passes(value)).collect()
+    }
+
+    fn props(layer: u8, group_key: i32, group_id: i32) -> SpriteProperties {
+        SpriteProperties {
+            layer,
+            group_key,
+            group_id,
+            shader: 0,
+        }
+    }
+
+    #[test]
+    fn passes() {
+        assert_eq!(passing(&facet(&[], &[], &[], false)), [0, 1, 2, 3, 4]);
+        assert_eq!(passing(&facet(&[], &[], &[], true)), [0, 1, 2, 3, 4]);
+        assert_eq!(passing(&facet(&[1, 3], &[], &[], false)), [1, 3]);
+        assert_eq!(passing(&facet(&[1, 3], &[], &[], true)), [0, 2, 4]);
+        // soloed values win over the selection, muted ones over everything
+        assert_eq!(passing(&facet(&[1, 3], &[2], &[], false)), [2]);
+        assert_eq!(passing(&facet(&[1, 3], &[2], &[], true)), [2]);
+        assert_eq!(passing(&facet(&[], &[2, 4], &[4], false)), [2]);
+        assert_eq!(passing(&facet(&[1, 3], &[], &[3], false)), [1]);
+        assert_eq!(passing(&facet(&[1, 3], &[], &[0], true)), [2, 4]);
+        assert_eq!(passing(&facet(&[], &[], &[0, 1], false)), [2, 3, 4]);
+    }
+
+    #[test]
+    fn changed() {
+        let entries: BTreeMap<u8, HashSet<Entity>> = [
+            (1, HashSet::from([Entity::from_raw(1), Entity::from_raw(2)])),
+            (2, HashSet::from([Entity::from_raw(3)])),
+        ]
+        .into();
+        let previous = facet(&[], &[], &[], false);
+        let changed: HashSet<Entity> = facet(&[2], &[], &[], false)
+            .changed(&previous, &entries)
+            .copied()
+            .collect();
+        assert_eq!(
+            changed,
+            HashSet::from([Entity::from_raw(1), Entity::from_raw(2)])
+        );
+        assert_eq!(previous.changed(&previous, &entries).count(), 0);
+    }
+
+    #[test]
+    fn index_insert_remove() {
+        let (a, b, c) = (
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        );
+        let mut index = SpriteIndex::default();
+        index.insert(a, &props(1, 10, 100));
+        index.insert(b, &props(1, 20, 100));
+        index.insert(c, &props(2, 20, 200));
+        assert_eq!(index.layers[&1], HashSet::from([a, b]));
+        assert_eq!(index.group_keys[&20], HashSet::from([b, c]));
+        assert_eq!(index.group_ids[&100], HashSet::from([a, b]));
+
+        index.remove(a, &props(1, 10, 100));
+        assert_eq!(index.layers[&1], HashSet::from([b]));
+        // values without sprites left aren't listed anymore
+        assert!(!index.group_keys.contains_key(&10));
+        assert_eq!(index.group_ids[&100], HashSet::from([b]));
+
+        // removing an entity that isn't indexed under the value leaves the others alone
+        index.remove(a, &props(2, 20, 200));
+        assert_eq!(index.layers[&2], HashSet::from([c]));
+        assert_eq!(index.group_keys[&20], HashSet::from([b, c]));
+
+        index.remove(b, &props(1, 20, 100));
+        index.remove(c, &props(2, 20, 200));
+        assert!(index.layers.is_empty());
+        assert!(index.group_keys.is_empty());
+        assert!(index.group_ids.is_empty());
+    }
+}
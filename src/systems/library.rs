@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContext};
+use futures_lite::future;
+use vakfu_format::map::element::{ElementLibrary, MapElement};
+use vakfu_format::rect::Rect;
+
+use super::browser::MapBrowser;
+use crate::commands::index::{ElementIndex, DEFAULT_INDEX};
+use crate::commands::GamePaths;
+
+/// Search over the element library, an empty text matches every id.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct ElementQuery {
+    text: String,
+    walkable: bool,
+    flip: bool,
+    before_mobile: bool,
+    animated: bool,
+}
+
+impl ElementQuery {
+    fn matches(&self, element: &MapElement) -> bool {
+        let text = self.text.trim();
+        let is_match = text.is_empty()
+            || text
+                .parse::<i32>()
+                .map(|id| element.id == id || element.texture_id == id)
+                .unwrap_or(false);
+        is_match
+            && (!self.walkable || element.flags.is_walkable())
+            && (!self.flip || element.flags.is_flip())
+            && (!self.before_mobile || element.flags.is_before_mobile())
+            && (!self.animated || element.animation.is_some())
+    }
+}
+
+/// Maps each element is placed on, from the element index brought up to date in the background.
+enum ElementUsage {
+    NotScanned,
+    Scanning(Task<ElementIndex>),
+    Scanned(ElementIndex),
+}
+
+/// Pages through every element of the library.
+pub struct LibraryBrowser {
+    query: ElementQuery,
+    page: usize,
+    selected: Option<i32>,
+    // textures of the current page by texture id, registered with egui
+    thumbnails: HashMap<i32, (Handle<Image>, egui::TextureId)>,
+    usage: ElementUsage,
+}
+
+impl Default for LibraryBrowser {
+    fn default() -> Self {
+        Self {
+            query: ElementQuery::default(),
+            page: 0,
+            selected: None,
+            thumbnails: HashMap::new(),
+            usage: ElementUsage::NotScanned,
+        }
+    }
+}
+
+impl LibraryBrowser {
+    const COLUMNS: usize = 6;
+    const PAGE_SIZE: usize = Self::COLUMNS * 4;
+    const THUMBNAIL_SIZE: f32 = 64.;
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn library_browser_system(
+    mut egui_context: ResMut<EguiContext>,
+    mut browser: ResMut<LibraryBrowser>,
+    mut maps: ResMut<MapBrowser>,
+    library: Res<ElementLibrary>,
+    asset_server: Res<AssetServer>,
+    pool: Res<AsyncComputeTaskPool>,
+    time: Res<Time>,
+) {
+    let browser = &mut *browser;
+    if let ElementUsage::Scanning(task) = &mut browser.usage {
+        if let Some(usage) = future::block_on(future::poll_once(task)) {
+            browser.usage = ElementUsage::Scanned(usage);
+        }
+    }
+
+    let elements: Vec<&MapElement> = library
+        .elements()
+        .iter()
+        .filter(|elem| browser.query.matches(elem))
+        .collect();
+    let last_page = elements.len().saturating_sub(1) / LibraryBrowser::PAGE_SIZE;
+    browser.page = browser.page.min(last_page);
+    let start = browser.page * LibraryBrowser::PAGE_SIZE;
+    let page = &elements[start..(start + LibraryBrowser::PAGE_SIZE).min(elements.len())];
+
+    // only the textures of the current page stay registered
+    browser.thumbnails.retain(|texture_id, (handle, _)| {
+        let is_shown = page.iter().any(|elem| elem.texture_id == *texture_id);
+        if !is_shown {
+            egui_context.remove_image(handle);
+        }
+        is_shown
+    });
+    for elem in page {
+        browser
+            .thumbnails
+            .entry(elem.texture_id)
+            .or_insert_with(|| {
+                let handle = asset_server.load(&format!("gfx/{}.tgam", elem.texture_id));
+                let id = egui_context.add_image(handle.clone());
+                (handle, id)
+            });
+    }
+
+    let ms = time.time_since_startup().as_millis() as u64;
+    let query = browser.query.clone();
+    let mut picked_map = None;
+
+    egui::Window::new("Elements").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Id or texture id");
+            ui.text_edit_singleline(&mut browser.query.text);
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut browser.query.walkable, "Walkable");
+            ui.checkbox(&mut browser.query.flip, "Flip");
+            ui.checkbox(&mut browser.query.before_mobile, "Before mobile");
+            ui.checkbox(&mut browser.query.animated, "Animated");
+        });
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                browser.page = browser.page.saturating_sub(1);
+            }
+            ui.label(format!(
+                "page {} of {}, {} elements",
+                browser.page + 1,
+                last_page + 1,
+                elements.len()
+            ));
+            if ui.button(">").clicked() {
+                browser.page += 1;
+            }
+        });
+
+        egui::Grid::new("elements").show(ui, |ui| {
+            for (idx, elem) in page.iter().enumerate() {
+                let (_, texture) = browser.thumbnails[&elem.texture_id];
+                let (size, uv) = thumbnail(elem, ms);
+                let button = egui::ImageButton::new(texture, size)
+                    .uv(uv)
+                    .selected(browser.selected == Some(elem.id));
+                let response = ui.add(button).on_hover_text(elem.id.to_string());
+                if response.clicked() {
+                    browser.selected = Some(elem.id);
+                }
+                if (idx + 1) % LibraryBrowser::COLUMNS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        let elem = match browser.selected.and_then(|id| library.get(id)) {
+            Some(elem) => elem,
+            None => return,
+        };
+        ui.separator();
+        ui.label(format!("element {}, texture {}", elem.id, elem.texture_id));
+        let size = elem.size();
+        let frames = elem
+            .animation
+            .as_ref()
+            .map_or(0, |frames| frames.frame_durations.len());
+        ui.label(format!("{} x {}, {} frames", size.x, size.y, frames));
+        match &browser.usage {
+            ElementUsage::Scanned(usage) => {
+                let used_in: Vec<i32> = usage.element(elem.id).map(|(map, _)| map).collect();
+                if used_in.is_empty() {
+                    ui.label("Not placed on any map");
+                }
+                ui.horizontal_wrapped(|ui| {
+                    for id in used_in {
+                        if ui
+                            .selectable_label(id == maps.current(), id.to_string())
+                            .clicked()
+                        {
+                            picked_map = Some(id);
+                        }
+                    }
+                });
+            }
+            _ => {
+                ui.label("Scanning maps...");
+            }
+        }
+    });
+
+    if browser.query != query {
+        browser.page = 0;
+    }
+    if let Some(id) = picked_map {
+        maps.request(id);
+    }
+    if browser.selected.is_some() && matches!(browser.usage, ElementUsage::NotScanned) {
+        let paths = maps.paths().clone();
+        let library = library.clone();
+        browser.usage =
+            ElementUsage::Scanning(pool.spawn(async move { scan_usage(&paths, &library) }));
+    }
+}
+
+/// Size and uv rect of an element thumbnail, showing the frame at `ms` of animated ones.
+fn thumbnail(element: &MapElement, ms: u64) -> (egui::Vec2, egui::Rect) {
+    let rect = match &element.animation {
+        Some(frames) => frames
+            .frame_rects
+            .get(frames.frame_at(ms))
+            .copied()
+            .unwrap_or_else(|| element.rect()),
+        None => element.rect(),
+    };
+    let size = rect.size();
+    let scale = (LibraryBrowser::THUMBNAIL_SIZE / size.max_element()).min(1.);
+
+    // atlas rects are relative to the element image size, not the texture size
+    let uv = Rect {
+        min: rect.min / element.image_size(),
+        max: rect.max / element.image_size(),
+    };
+    let (left, right) = if element.flags.is_flip() {
+        (uv.max.x, uv.min.x)
+    } else {
+        (uv.min.x, uv.max.x)
+    };
+    let uv = egui::Rect::from_min_max(egui::pos2(left, uv.min.y), egui::pos2(right, uv.max.y));
+    (egui::vec2(size.x * scale, size.y * scale), uv)
+}
+
+/// Updates the element index shared with the `index` and `find` commands, only the maps that
+/// changed since it was saved are scanned again.
+fn scan_usage(paths: &GamePaths, library: &ElementLibrary) -> ElementIndex {
+    let path = Path::new(DEFAULT_INDEX);
+    let mut index = ElementIndex::open(path);
+    let saved = index.update(paths, library).and_then(|_| index.save(path));
+    if let Err(err) = saved {
+        eprintln!("failed to update the element index: {:#}", err);
+    }
+    index
+}
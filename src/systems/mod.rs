@@ -1,5 +1,14 @@
+pub mod blend;
+pub mod browser;
 pub mod camera;
+pub mod diff;
+pub mod filter;
+pub mod library;
+pub mod overlay;
+pub mod path;
+pub mod picking;
 pub mod render;
 pub mod settings;
 pub mod setup;
+pub mod stream;
 pub mod ui;
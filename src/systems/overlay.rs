@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use glam::const_vec2;
+use vakfu_format::map::iso_to_screen;
+use vakfu_format::map::walkability::Walkability;
+
+use super::render::VisibilityFlags;
+use super::settings::Settings;
+
+/// Marks the entities drawn by the walkability overlay.
+#[derive(Debug, Default, Component)]
+pub struct CellOverlay;
+
+/// Textures of the walkability overlay.
+pub struct OverlayAssets {
+    cell: Handle<Image>,
+    corner: Handle<Image>,
+}
+
+impl OverlayAssets {
+    // above every map sprite, their depth is below 1
+    const CELL_Z: f32 = 2.;
+    const CORNER_Z: f32 = 2.1;
+
+    const WALKABLE: Color = Color::rgba(0.2, 0.9, 0.2, 0.35);
+    const BLOCKED: Color = Color::rgba(0.9, 0.2, 0.2, 0.35);
+    const SLOPE: Color = Color::rgba(1.0, 0.9, 0.1, 0.9);
+
+    // corners of a cell relative to its center, slope bit 0 is the top corner going clockwise
+    const CORNERS: [Vec2; 4] = [
+        const_vec2!([0., 21.5]),
+        const_vec2!([43., 0.]),
+        const_vec2!([0., -21.5]),
+        const_vec2!([-43., 0.]),
+    ];
+}
+
+impl OverlayAssets {
+    /// White cell shaped texture to tint.
+    #[inline]
+    pub fn cell(&self) -> Handle<Image> {
+        self.cell.clone()
+    }
+}
+
+pub fn overlay_setup_system(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let cell = images.add(new_mask(86, 43, |x, y| {
+        x.abs() / 43. + y.abs() / 21.5 <= 1.
+    }));
+    let corner = images.add(new_mask(8, 8, |x, y| x * x + y * y <= 16.));
+    commands.insert_resource(OverlayAssets { cell, corner });
+}
+
+/// Spawns the overlay for the cells of a chunk.
+pub fn spawn_cell_overlay(
+    commands: &mut Commands,
+    assets: &OverlayAssets,
+    walkability: &Walkability,
+    settings: &Settings,
+) -> Vec<Entity> {
+    let mut entities = vec![];
+    for (cell, state) in walkability.cells() {
+        let center = iso_to_screen(cell, state.z.into());
+        let color = if state.walkable {
+            OverlayAssets::WALKABLE
+        } else {
+            OverlayAssets::BLOCKED
+        };
+        let entity = spawn_overlay_sprite(
+            commands,
+            assets.cell.clone(),
+            center.extend(OverlayAssets::CELL_Z),
+            color,
+            settings,
+        );
+        entities.push(entity);
+
+        for (bit, corner) in OverlayAssets::CORNERS.iter().enumerate() {
+            if state.slope & (1 << bit) != 0 {
+                let entity = spawn_overlay_sprite(
+                    commands,
+                    assets.corner.clone(),
+                    (center + *corner).extend(OverlayAssets::CORNER_Z),
+                    OverlayAssets::SLOPE,
+                    settings,
+                );
+                entities.push(entity);
+            }
+        }
+    }
+    entities
+}
+
+fn spawn_overlay_sprite(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    translation: Vec3,
+    color: Color,
+    settings: &Settings,
+) -> Entity {
+    let visibility_flags = VisibilityFlags {
+        is_active: settings.walkability_overlay,
+        ..Default::default()
+    };
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color,
+                ..Default::default()
+            },
+            texture,
+            transform: Transform::from_translation(translation),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(visibility_flags)
+        .insert(CellOverlay)
+        .id()
+}
+
+/// White image with an alpha mask, `inside` is given coordinates relative to the center.
+fn new_mask(width: u32, height: u32, inside: impl Fn(f32, f32) -> bool) -> Image {
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f32 + 0.5 - width as f32 / 2.;
+            let dy = y as f32 + 0.5 - height as f32 / 2.;
+            let alpha = if inside(dx, dy) { u8::MAX } else { 0 };
+            data.extend([u8::MAX, u8::MAX, u8::MAX, alpha]);
+        }
+    }
+    let extent = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    Image::new(
+        extent,
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    )
+}
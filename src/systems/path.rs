@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use vakfu_format::map::iso_to_screen;
+use vakfu_format::map::path::find_path;
+
+use super::overlay::OverlayAssets;
+use super::picking::HoveredCell;
+use super::stream::ChunkStream;
+use crate::commands::path::DEFAULT_MAX_STEP;
+
+/// Path between two cells picked in the view, searched through the loaded chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTool {
+    pub picking: bool,
+    pub max_step: i16,
+    pub start: Option<IVec2>,
+    pub end: Option<IVec2>,
+    /// Number of steps of the last path, `None` when there was none.
+    pub steps: Option<usize>,
+    pub updated: bool,
+}
+
+impl Default for PathTool {
+    fn default() -> Self {
+        Self {
+            picking: false,
+            max_step: DEFAULT_MAX_STEP,
+            start: None,
+            end: None,
+            steps: None,
+            updated: false,
+        }
+    }
+}
+
+/// Marks the cells drawn for the path.
+#[derive(Debug, Default, Component)]
+pub struct PathMarker;
+
+impl PathMarker {
+    // above the walkability overlay
+    const Z: f32 = 2.5;
+
+    const ENDPOINT: Color = Color::rgba(0.1, 0.4, 1.0, 0.8);
+    const STEP: Color = Color::rgba(0.2, 0.7, 1.0, 0.5);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn path_tool_system(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut tool: ResMut<PathTool>,
+    buttons: Res<Input<MouseButton>>,
+    hovered: Res<HoveredCell>,
+    stream: Res<ChunkStream>,
+    overlay: Res<OverlayAssets>,
+    markers: Query<Entity, With<PathMarker>>,
+) {
+    if tool.picking
+        && buttons.just_pressed(MouseButton::Left)
+        && !egui_context.ctx_mut().wants_pointer_input()
+    {
+        if let Some((cell, _)) = hovered.cell {
+            // a click after a complete pair starts a new path
+            if tool.start.is_none() || tool.end.is_some() {
+                tool.start = Some(cell);
+                tool.end = None;
+            } else {
+                tool.end = Some(cell);
+            }
+            tool.updated = true;
+        }
+    }
+    if !tool.updated {
+        return;
+    }
+    tool.updated = false;
+
+    for entity in markers.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let walkability = stream.walkability();
+    let path = match (tool.start, tool.end) {
+        (Some(start), Some(end)) => find_path(walkability, start, end, tool.max_step),
+        _ => None,
+    };
+    tool.steps = path.as_ref().map(|path| path.len() - 1);
+
+    let endpoints = tool.start.into_iter().chain(tool.end);
+    let cells = path.unwrap_or_else(|| endpoints.collect());
+    for (idx, cell) in cells.iter().enumerate() {
+        let z = stream.cell_top(*cell).unwrap_or_default();
+        let color = if idx == 0 || idx == cells.len() - 1 {
+            PathMarker::ENDPOINT
+        } else {
+            PathMarker::STEP
+        };
+        let pos = iso_to_screen(*cell, z.into()).extend(PathMarker::Z);
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    ..Default::default()
+                },
+                texture: overlay.cell(),
+                transform: Transform::from_translation(pos),
+                ..Default::default()
+            })
+            .insert(PathMarker);
+    }
+}
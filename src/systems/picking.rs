@@ -0,0 +1,137 @@
+use bevy::core::FloatOrd;
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use vakfu_format::map::screen_to_iso;
+
+use super::blend::BlendedSprite;
+use super::camera::cursor_to_world;
+use super::diff::DiffGhost;
+use super::path::PathTool;
+use super::stream::ChunkStream;
+
+/// Sprite picked by clicking in the view.
+#[derive(Debug, Default)]
+pub struct Selection {
+    pub entity: Option<Entity>,
+}
+
+/// Cell under the cursor, along with the z of its topmost sprite.
+#[derive(Debug, Default)]
+pub struct HoveredCell {
+    pub cell: Option<(IVec2, i16)>,
+}
+
+type PickableSprite<'a> = (
+    Entity,
+    &'a Transform,
+    Option<&'a TextureAtlasSprite>,
+    Option<&'a BlendedSprite>,
+    &'a Handle<TextureAtlas>,
+    &'a Visibility,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub fn picking_system(
+    mut egui_context: ResMut<EguiContext>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    cameras: Query<&Transform, With<Camera>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    images: Res<Assets<Image>>,
+    sprites: Query<PickableSprite, Without<DiffGhost>>,
+    path_tool: Res<PathTool>,
+    mut selection: ResMut<Selection>,
+) {
+    // clicks pick cells for the path tool instead
+    if path_tool.picking
+        || !buttons.just_pressed(MouseButton::Left)
+        || egui_context.ctx_mut().wants_pointer_input()
+    {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let point = match cursor_to_world(window, cameras.single()) {
+        Some(point) => point,
+        None => return,
+    };
+
+    // the topmost sprite has the highest z
+    selection.entity = sprites
+        .iter()
+        .filter(|(_, _, _, _, _, visibility)| visibility.is_visible)
+        .filter(|(_, transform, sprite, blended, atlas, _)| {
+            let frame = match (sprite, blended) {
+                (Some(sprite), _) => (sprite.index, sprite.flip_x),
+                (None, Some(blended)) => (blended.index, blended.flip_x),
+                (None, None) => return false,
+            };
+            atlases
+                .get(*atlas)
+                .map(|atlas| hit_test(point, transform, frame, atlas, &images))
+                .unwrap_or(false)
+        })
+        .max_by_key(|(_, transform, _, _, _, _)| FloatOrd(transform.translation.z))
+        .map(|(entity, _, _, _, _, _)| entity);
+}
+
+pub fn hover_system(
+    windows: Res<Windows>,
+    cameras: Query<&Transform, With<Camera>>,
+    stream: Res<ChunkStream>,
+    mut hovered: ResMut<HoveredCell>,
+) {
+    let point = windows
+        .get_primary()
+        .and_then(|window| cursor_to_world(window, cameras.single()));
+
+    hovered.cell = point.and_then(|point| {
+        screen_to_iso(point, stream.source().heights())
+            .into_iter()
+            .filter(|(cell, height)| stream.cell_top(*cell) == Some(*height as i16))
+            // cells further down the screen are in front of the ones behind them
+            .max_by_key(|(cell, height)| (cell.x + cell.y, *height))
+            .map(|(cell, height)| (cell, height as i16))
+    });
+}
+
+/// Tests a point against the atlas rect of a sprite frame and the alpha of its texture,
+/// `frame` is the index of the rect and whether it's flipped.
+fn hit_test(
+    point: Vec2,
+    transform: &Transform,
+    (index, flip_x): (usize, bool),
+    atlas: &TextureAtlas,
+    images: &Assets<Image>,
+) -> bool {
+    let rect = match atlas.textures.get(index) {
+        Some(rect) => *rect,
+        None => return false,
+    };
+    // sprites are anchored at their top-left corner and the y axis points up
+    let local = point - transform.translation.truncate();
+    let (x, y) = (local.x, -local.y);
+    let size = rect.size();
+    if x < 0. || y < 0. || x >= size.x || y >= size.y {
+        return false;
+    }
+
+    let image = match images.get(&atlas.texture) {
+        Some(image) => image,
+        // texture isn't loaded yet, the rect is all there is to test
+        None => return true,
+    };
+    let x = if flip_x { size.x - 1. - x } else { x };
+    // atlas rects are relative to the element image size, the texture is padded past it
+    let extent = image.texture_descriptor.size;
+    let texel_scale = Vec2::new(extent.width as f32, extent.height as f32) / atlas.size;
+    let u = ((rect.min.x + x + 0.5) * texel_scale.x) as usize;
+    let v = ((rect.min.y + y + 0.5) * texel_scale.y) as usize;
+    if u >= extent.width as usize {
+        return false;
+    }
+    let idx = (v * extent.width as usize + u) * 4 + 3;
+    image.data.get(idx).map(|alpha| *alpha > 0).unwrap_or(true)
+}
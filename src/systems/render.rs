@@ -1,9 +1,10 @@
 use bevy::prelude::*;
 use bevy::sprite::Rect;
-
-use crate::map::chunk::MapChunk;
-use crate::map::frames::Frames;
-use crate::map::iso_to_screen;
+use itertools::iproduct;
+use vakfu_format::map::chunk::ChunkHeader;
+use vakfu_format::map::frames::{frame_at, Frames};
+use vakfu_format::map::iso_to_screen;
+use vakfu_format::map::sprite::MapSprite;
 
 #[derive(Default, Component)]
 pub struct MapChunkView {
@@ -15,24 +16,50 @@ pub struct MapChunkView {
 impl MapChunkView {
     const CHUNK_HEIGHT: i32 = 200;
 
-    pub fn new(chunk: &MapChunk, children: Vec<Entity>) -> Self {
-        let rect = Rect {
-            min: iso_to_screen(IVec2::new(chunk.min_x, chunk.min_y), -Self::CHUNK_HEIGHT),
-            max: iso_to_screen(IVec2::new(chunk.max_x, chunk.max_y), Self::CHUNK_HEIGHT),
-        };
+    pub fn new(chunk: &ChunkHeader, children: Vec<Entity>) -> Self {
         Self {
-            rect,
+            rect: Self::chunk_rect(chunk),
             children,
             previously_visible: false,
         }
     }
+
+    /// Screen area covered by a chunk, the corners of its cells are all projected since the
+    /// ones at the min and max cells share the same screen x on square chunks.
+    pub fn chunk_rect(chunk: &ChunkHeader) -> Rect {
+        let corners = iproduct!(
+            [chunk.min_x, chunk.max_x],
+            [chunk.min_y, chunk.max_y],
+            [-Self::CHUNK_HEIGHT, Self::CHUNK_HEIGHT]
+        )
+        .map(|(x, y, height)| iso_to_screen(IVec2::new(x, y), height));
+        let mut rect = Rect {
+            min: Vec2::splat(f32::MAX),
+            max: Vec2::splat(f32::MIN),
+        };
+        for corner in corners {
+            rect.min = rect.min.min(corner);
+            rect.max = rect.max.max(corner);
+        }
+        rect
+    }
+
+    #[inline]
+    pub fn children(&self) -> &[Entity] {
+        &self.children
+    }
 }
 
+/// Area of the map around the camera, updated by `map_chunk_view_system`.
+#[derive(Debug, Default)]
+pub struct ViewRect(pub Rect);
+
 pub fn map_chunk_view_system(
     windows: Res<Windows>,
     cameras: Query<&mut Transform, With<Camera>>,
     mut sprites: Query<&mut VisibilityFlags>,
     mut chunks: Query<&mut MapChunkView>,
+    mut view: ResMut<ViewRect>,
 ) {
     let camera = cameras.single();
     let window_size = if let Some(window) = windows.get_primary() {
@@ -44,6 +71,7 @@ pub fn map_chunk_view_system(
         min: camera.translation.truncate() - window_size,
         max: camera.translation.truncate() + window_size,
     };
+    view.0 = camera_rect;
 
     for mut chunk in chunks.iter_mut() {
         let visible = does_intersect(camera_rect, chunk.rect);
@@ -81,10 +109,21 @@ pub fn visibility_system(
     }
 }
 
-#[derive(Debug, Default, Component)]
+#[derive(Debug, Default, Clone, Copy, Component)]
 pub struct SpriteProperties {
     pub layer: u8,
     pub group_key: i32,
+    pub group_id: i32,
+    pub shader: u8,
+}
+
+/// Map sprite an entity was spawned from, along with the position of its chunk and its index
+/// in the sprites of the chunk.
+#[derive(Debug, Default, Component)]
+pub struct SpriteSource {
+    pub sprite: MapSprite,
+    pub chunk: (i32, i32),
+    pub index: usize,
 }
 
 #[derive(Debug, Default, Bundle)]
@@ -96,6 +135,7 @@ pub struct StaticSpriteBundle {
     pub visibility: Visibility,
     pub properties: SpriteProperties,
     pub visibility_flags: VisibilityFlags,
+    pub source: SpriteSource,
 }
 
 #[derive(Debug, Default, Bundle)]
@@ -108,6 +148,7 @@ pub struct AnimatedSpriteBundle {
     pub visibility: Visibility,
     pub properties: SpriteProperties,
     pub visibility_flags: VisibilityFlags,
+    pub source: SpriteSource,
 }
 
 #[derive(Debug, Default, Component)]
@@ -123,6 +164,12 @@ impl Animation {
             frame_times: frames.frame_times.clone(),
         }
     }
+
+    /// Index of the frame shown `ms` milliseconds into the animation.
+    #[inline]
+    pub fn frame(&self, ms: u64) -> usize {
+        frame_at(&self.frame_times, self.total_time, ms)
+    }
 }
 
 pub fn animation_system(
@@ -134,16 +181,11 @@ pub fn animation_system(
         if !visibility.is_visible {
             continue;
         }
-        let passed = ms % anim.total_time as u64;
-        let index = anim
-            .frame_times
-            .binary_search(&(passed as u16))
-            .unwrap_or_else(|i| i - 1);
-        sprite.index = index;
+        sprite.index = anim.frame(ms);
     }
 }
 
 #[inline]
-fn does_intersect(r1: Rect, r2: Rect) -> bool {
+pub fn does_intersect(r1: Rect, r2: Rect) -> bool {
     !(r1.max.x < r2.min.x || r2.max.x < r1.min.x || r1.max.y < r2.min.y || r2.max.y < r1.min.y)
 }
@@ -1,30 +1,111 @@
 use bevy::prelude::*;
+use vakfu_format::map::sprite::MapSprite;
 
-use super::render::{SpriteProperties, VisibilityFlags};
+use super::blend::BlendMaterial;
+use super::filter::Facet;
+use super::overlay::CellOverlay;
+use super::render::{SpriteProperties, SpriteSource, VisibilityFlags};
+use super::stream::ChunkStream;
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct Settings {
-    pub layer_filter_on: bool,
-    pub layer: u8,
-    pub group_filter_on: bool,
-    pub group: i32,
+    pub layers: Facet<u8>,
+    pub group_keys: Facet<i32>,
+    pub group_ids: Facet<i32>,
+    pub walkability_overlay: bool,
+    pub color_by_shader: bool,
+    pub render_order: bool,
     pub updated: bool,
 }
 
+impl Settings {
+    pub fn is_active(&self, props: &SpriteProperties) -> bool {
+        self.layers.passes(&props.layer)
+            && self.group_keys.passes(&props.group_key)
+            && self.group_ids.passes(&props.group_id)
+    }
+
+    /// The color a sprite is drawn with, its own tint unless sprites are colored by shader.
+    pub fn sprite_color(&self, sprite: &MapSprite, shader: u8) -> Color {
+        if self.color_by_shader {
+            shader_color(shader)
+        } else {
+            sprite.color().into()
+        }
+    }
+}
+
+/// Debug color of a shader id, the default shader is gray so the others stand out.
+pub fn shader_color(shader: u8) -> Color {
+    match shader {
+        0 => Color::GRAY,
+        // spread the hues by the golden angle
+        _ => Color::hsl((shader as f32 * 137.5) % 360., 0.9, 0.6),
+    }
+}
+
+type SpriteColor<'a> = (
+    &'a SpriteProperties,
+    &'a SpriteSource,
+    Option<&'a mut TextureAtlasSprite>,
+    Option<&'a Handle<BlendMaterial>>,
+);
+
+#[allow(clippy::too_many_arguments)]
 pub fn settings_system(
     mut settings: ResMut<Settings>,
-    mut query: Query<(&SpriteProperties, &mut VisibilityFlags)>,
+    mut previous: Local<Settings>,
+    stream: Res<ChunkStream>,
+    mut query: Query<(&SpriteProperties, &mut VisibilityFlags), Without<CellOverlay>>,
+    mut overlays: Query<&mut VisibilityFlags, With<CellOverlay>>,
+    mut colors: Query<SpriteColor>,
+    mut materials: ResMut<Assets<BlendMaterial>>,
 ) {
     if settings.updated {
-        for (props, mut visibility) in query.iter_mut() {
-            let group_range = settings.group * 1000..(settings.group + 1) * 1000;
+        // only the sprites with values that changed between shown and hidden are updated
+        let index = stream.index();
+        let changed = settings
+            .layers
+            .changed(&previous.layers, &index.layers)
+            .chain(
+                settings
+                    .group_keys
+                    .changed(&previous.group_keys, &index.group_keys),
+            )
+            .chain(
+                settings
+                    .group_ids
+                    .changed(&previous.group_ids, &index.group_ids),
+            );
+        for entity in changed {
+            if let Ok((props, mut visibility)) = query.get_mut(*entity) {
+                let is_active = settings.is_active(props);
+                if visibility.is_active != is_active {
+                    visibility.is_active = is_active;
+                }
+            }
+        }
+
+        if previous.walkability_overlay != settings.walkability_overlay {
+            for mut visibility in overlays.iter_mut() {
+                visibility.is_active = settings.walkability_overlay;
+            }
+        }
 
-            let is_layer_active = !settings.layer_filter_on || props.layer == settings.layer;
-            let is_group_active =
-                !settings.group_filter_on || group_range.contains(&props.group_key);
-            visibility.is_active = is_layer_active && is_group_active;
+        // changing a material has it prepared again, so only recolor when toggled
+        if previous.color_by_shader != settings.color_by_shader {
+            for (props, source, sprite, material) in colors.iter_mut() {
+                let color = settings.sprite_color(&source.sprite, props.shader);
+                if let Some(mut sprite) = sprite {
+                    sprite.color = color;
+                }
+                if let Some(material) = material.and_then(|handle| materials.get_mut(handle)) {
+                    material.color = color;
+                }
+            }
         }
 
         settings.updated = false;
+        *previous = settings.clone();
     }
 }
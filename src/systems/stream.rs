@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::sprite::{Anchor, Mesh2dHandle, Rect};
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use vakfu_format::error::FormatError;
+use vakfu_format::map::chunk::MapChunk;
+use vakfu_format::map::element::{BlendMode, ElementLibrary, MapElement};
+use vakfu_format::map::source::MapSource;
+use vakfu_format::map::walkability::Walkability;
+
+use super::blend::{frame_meshes, BlendMaterial, BlendedSprite, BlendedSpriteBundle};
+use super::filter::{SpriteCounts, SpriteIndex};
+use super::overlay::{spawn_cell_overlay, OverlayAssets};
+use super::render::{does_intersect, MapChunkView, ViewRect};
+use super::settings::Settings;
+use crate::systems::render::{
+    AnimatedSpriteBundle, Animation, SpriteProperties, SpriteSource, StaticSpriteBundle,
+    VisibilityFlags,
+};
+
+/// Chunks of the map, decoded in the background as the camera nears them
+/// and despawned once they are far outside of the view.
+pub struct ChunkStream {
+    source: Arc<MapSource>,
+    chunks: HashMap<(i32, i32), ChunkState>,
+    atlases: HashMap<i32, Handle<TextureAtlas>>,
+    // frame quads of the elements drawn with a blend material
+    meshes: HashMap<i32, Vec<Mesh2dHandle>>,
+    // cells of the loaded chunks
+    walkability: Walkability,
+    index: SpriteIndex,
+    counts: SpriteCountState,
+}
+
+/// Counts of the sprites of the whole map, scanned in the background.
+enum SpriteCountState {
+    NotScanned,
+    Scanning(Task<SpriteCounts>),
+    Scanned(SpriteCounts),
+}
+
+enum ChunkState {
+    Loading(Task<Result<MapChunk, FormatError>>),
+    // the entity of the chunk view and the cells covered by its sprites
+    Loaded(LoadedChunk),
+    Failed,
+}
+
+struct LoadedChunk {
+    view: Entity,
+    // cells covered by its sprites
+    cells: Vec<IVec2>,
+    sprites: Vec<(Entity, SpriteProperties)>,
+}
+
+impl ChunkStream {
+    // margins around the view rect, relative to its size
+    const LOAD_MARGIN: f32 = 0.5;
+    const UNLOAD_MARGIN: f32 = 1.5;
+
+    pub fn new(source: MapSource) -> Self {
+        Self {
+            source: Arc::new(source),
+            chunks: HashMap::new(),
+            atlases: HashMap::new(),
+            meshes: HashMap::new(),
+            walkability: Walkability::default(),
+            index: SpriteIndex::default(),
+            counts: SpriteCountState::NotScanned,
+        }
+    }
+
+    #[inline]
+    pub fn source(&self) -> &MapSource {
+        &self.source
+    }
+
+    /// Cells of the chunks that are currently loaded.
+    #[inline]
+    pub fn walkability(&self) -> &Walkability {
+        &self.walkability
+    }
+
+    /// Sprites of the chunks that are currently loaded.
+    #[inline]
+    pub fn index(&self) -> &SpriteIndex {
+        &self.index
+    }
+
+    /// Sprites of the whole map by filtered value, once they are counted.
+    #[inline]
+    pub fn counts(&self) -> Option<&SpriteCounts> {
+        match &self.counts {
+            SpriteCountState::Scanned(counts) => Some(counts),
+            _ => None,
+        }
+    }
+
+    /// Top z of a cell, if the chunk it's in is loaded.
+    #[inline]
+    pub fn cell_top(&self, cell: IVec2) -> Option<i16> {
+        self.walkability.get(cell).map(|state| state.z)
+    }
+
+    /// Despawns all chunks of the current map and starts streaming from another one.
+    /// Atlases and meshes are kept since they only depend on the element library.
+    pub fn replace(
+        &mut self,
+        source: MapSource,
+        commands: &mut Commands,
+        chunk_views: &Query<&MapChunkView>,
+    ) {
+        for (_, state) in self.chunks.drain() {
+            if let ChunkState::Loaded(chunk) = state {
+                despawn_chunk(commands, chunk.view, chunk_views);
+            }
+        }
+        self.walkability.clear();
+        self.index.clear();
+        // dropping a pending scan cancels it
+        self.counts = SpriteCountState::NotScanned;
+        self.source = Arc::new(source);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn chunk_stream_system(
+    mut commands: Commands,
+    mut stream: ResMut<ChunkStream>,
+    view: Res<ViewRect>,
+    settings: Res<Settings>,
+    library: Res<ElementLibrary>,
+    asset_server: Res<AssetServer>,
+    overlay: Res<OverlayAssets>,
+    pool: Res<AsyncComputeTaskPool>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<BlendMaterial>>,
+    chunk_views: Query<&MapChunkView>,
+) {
+    let ChunkStream {
+        source,
+        chunks,
+        atlases: atlas_cache,
+        meshes: mesh_cache,
+        walkability,
+        index,
+        counts,
+    } = &mut *stream;
+
+    match counts {
+        SpriteCountState::NotScanned => {
+            let source = source.clone();
+            *counts =
+                SpriteCountState::Scanning(pool.spawn(async move { SpriteCounts::scan(&source) }));
+        }
+        SpriteCountState::Scanning(task) => {
+            if let Some(scanned) = future::block_on(future::poll_once(task)) {
+                *counts = SpriteCountState::Scanned(scanned);
+            }
+        }
+        SpriteCountState::Scanned(_) => {}
+    }
+
+    let load_rect = grow(view.0, ChunkStream::LOAD_MARGIN);
+    let unload_rect = grow(view.0, ChunkStream::UNLOAD_MARGIN);
+
+    for header in source.chunks() {
+        let pos = (header.map_x, header.map_y);
+        let rect = MapChunkView::chunk_rect(header);
+        if does_intersect(load_rect, rect) {
+            chunks.entry(pos).or_insert_with(|| {
+                let source = source.clone();
+                ChunkState::Loading(pool.spawn(async move { source.load(pos.0, pos.1) }))
+            });
+        } else if !does_intersect(unload_rect, rect) {
+            // dropping a pending task cancels it
+            if let Some(ChunkState::Loaded(loaded)) = chunks.remove(&pos) {
+                despawn_chunk(&mut commands, loaded.view, &chunk_views);
+                for cell in loaded.cells {
+                    walkability.remove(cell);
+                }
+                for (entity, props) in loaded.sprites {
+                    index.remove(entity, &props);
+                }
+            }
+        }
+    }
+
+    for state in chunks.values_mut() {
+        let task = match state {
+            ChunkState::Loading(task) => task,
+            _ => continue,
+        };
+        *state = match future::block_on(future::poll_once(task)) {
+            None => continue,
+            Some(Ok(chunk)) => {
+                let mut elements = vec![];
+                let mut sprites = vec![];
+                let depths = source.depths(&chunk);
+                for ((idx, sprite), z_pos) in chunk.sprites.iter().enumerate().zip(depths) {
+                    if let Some(elem) = library.get(sprite.element_id) {
+                        let texture = asset_server.load(&format!("gfx/{}.tgam", elem.texture_id));
+
+                        let handle = atlas_cache.entry(elem.id).or_insert_with(|| {
+                            let rects = elem
+                                .animation
+                                .as_ref()
+                                .map(|frames| frames.frame_rects.as_slice());
+                            let atlas = new_atlas(
+                                texture.clone(),
+                                elem.image_size(),
+                                rects.unwrap_or(&[elem.rect()]),
+                            );
+                            atlases.add(atlas)
+                        });
+                        let blend = match elem.blend_mode() {
+                            BlendMode::Alpha => None,
+                            mode => {
+                                let frames = mesh_cache
+                                    .entry(elem.id)
+                                    .or_insert_with(|| frame_meshes(elem, &mut meshes));
+                                let material = materials.add(BlendMaterial {
+                                    texture,
+                                    color: settings.sprite_color(sprite, elem.shader),
+                                    mode,
+                                });
+                                Some((frames.clone(), material))
+                            }
+                        };
+                        let source = SpriteSource {
+                            sprite: sprite.clone(),
+                            chunk: (chunk.map_x, chunk.map_y),
+                            index: idx,
+                        };
+                        let (entity, props) = spawn_sprite(
+                            &mut commands,
+                            source,
+                            elem,
+                            handle.clone(),
+                            blend,
+                            z_pos,
+                            &settings,
+                        );
+                        index.insert(entity, &props);
+                        elements.push(entity);
+                        sprites.push((entity, props));
+                    }
+                }
+
+                let chunk_cells = Walkability::from_chunks([&chunk], &library);
+                elements.extend(spawn_cell_overlay(
+                    &mut commands,
+                    &overlay,
+                    &chunk_cells,
+                    &settings,
+                ));
+                let cells = chunk_cells.cells().map(|(cell, _)| cell).collect();
+                walkability.merge(chunk_cells);
+
+                let view = MapChunkView::new(&chunk.header(), elements);
+                ChunkState::Loaded(LoadedChunk {
+                    view: commands.spawn().insert(view).id(),
+                    cells,
+                    sprites,
+                })
+            }
+            Some(Err(err)) => {
+                eprintln!("{}", err);
+                ChunkState::Failed
+            }
+        };
+    }
+}
+
+fn despawn_chunk(commands: &mut Commands, entity: Entity, chunk_views: &Query<&MapChunkView>) {
+    if let Ok(view) = chunk_views.get(entity) {
+        for child in view.children() {
+            commands.entity(*child).despawn();
+        }
+    }
+    commands.entity(entity).despawn();
+}
+
+fn spawn_sprite(
+    commands: &mut Commands,
+    source: SpriteSource,
+    element: &MapElement,
+    texture_atlas: Handle<TextureAtlas>,
+    blend: Option<(Vec<Mesh2dHandle>, Handle<BlendMaterial>)>,
+    z_order: f32,
+    settings: &Settings,
+) -> (Entity, SpriteProperties) {
+    let sprite = &source.sprite;
+    let color = settings.sprite_color(sprite, element.shader);
+    let pos = sprite.anchor_position(element);
+    let transform = Transform::from_translation(pos.extend(z_order));
+    let visibility = Visibility { is_visible: false };
+    let properties = SpriteProperties {
+        layer: sprite.layer,
+        group_key: sprite.group_key,
+        group_id: sprite.group_id,
+        shader: element.shader,
+    };
+    let visibility_flags = VisibilityFlags {
+        is_active: settings.is_active(&properties),
+        ..Default::default()
+    };
+    if let Some((frames, material)) = blend {
+        let sprite = BlendedSprite::new(frames, element.flags.is_flip());
+        let mut entity = commands.spawn_bundle(BlendedSpriteBundle {
+            mesh: sprite.mesh(),
+            sprite,
+            material,
+            texture_atlas,
+            transform,
+            visibility,
+            properties,
+            visibility_flags,
+            source,
+            ..Default::default()
+        });
+        if let Some(frames) = &element.animation {
+            entity.insert(Animation::new(frames));
+        }
+        return (entity.id(), properties);
+    }
+
+    let sprite = TextureAtlasSprite {
+        flip_x: element.flags.is_flip(),
+        color,
+        anchor: Anchor::TopLeft,
+        ..Default::default()
+    };
+
+    let entity = match &element.animation {
+        None => commands
+            .spawn_bundle(StaticSpriteBundle {
+                sprite,
+                texture_atlas,
+                transform,
+                visibility,
+                properties,
+                visibility_flags,
+                source,
+                ..Default::default()
+            })
+            .id(),
+        Some(frames) => {
+            let animation = Animation::new(frames);
+            commands
+                .spawn_bundle(AnimatedSpriteBundle {
+                    sprite,
+                    texture_atlas,
+                    transform,
+                    animation,
+                    visibility,
+                    properties,
+                    visibility_flags,
+                    source,
+                    ..Default::default()
+                })
+                .id()
+        }
+    };
+    (entity, properties)
+}
+
+pub fn new_atlas(
+    image: Handle<Image>,
+    size: Vec2,
+    rects: &[vakfu_format::rect::Rect],
+) -> TextureAtlas {
+    let mut atlas = TextureAtlas::new_empty(image, size);
+    for rect in rects {
+        atlas.add_texture((*rect).into());
+    }
+    atlas
+}
+
+#[inline]
+fn grow(rect: Rect, factor: f32) -> Rect {
+    let margin = rect.size() * factor;
+    Rect {
+        min: rect.min - margin,
+        max: rect.max + margin,
+    }
+}
@@ -1,29 +1,310 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::core::FloatOrd;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext};
+use vakfu_format::map::element::{BlendMode, ElementLibrary};
 
-use crate::systems::settings::Settings;
+use crate::systems::browser::MapBrowser;
+use crate::systems::camera::world_to_window;
+use crate::systems::filter::Facet;
+use crate::systems::path::PathTool;
+use crate::systems::picking::{HoveredCell, Selection};
+use crate::systems::render::SpriteSource;
+use crate::systems::settings::{shader_color, Settings};
+use crate::systems::stream::ChunkStream;
 
-pub fn ui_system(mut egui_context: ResMut<EguiContext>, mut settings: ResMut<Settings>) {
+pub fn ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    mut settings: ResMut<Settings>,
+    mut browser: ResMut<MapBrowser>,
+    library: Res<ElementLibrary>,
+    stream: Res<ChunkStream>,
+    mut shaders: Local<Vec<u8>>,
+) {
+    if shaders.is_empty() {
+        *shaders = library.elements().iter().map(|elem| elem.shader).collect();
+        shaders.sort_unstable();
+        shaders.dedup();
+    }
     let copy = settings.clone();
 
     egui::Window::new("Settings").show(egui_context.ctx_mut(), |ui| {
-        ui.horizontal(|ui| {
-            ui.checkbox(&mut settings.layer_filter_on, "Layer filter");
-            ui.add_enabled(
-                settings.layer_filter_on,
-                egui::Slider::new(&mut settings.layer, 0..=127),
-            );
+        ui.checkbox(&mut settings.walkability_overlay, "Walkability overlay");
+        ui.checkbox(&mut settings.render_order, "Render order");
+        ui.checkbox(&mut settings.color_by_shader, "Color by shader");
+        if settings.color_by_shader {
+            for &shader in shaders.iter() {
+                let [r, g, b, _] = shader_color(shader).as_rgba_f32();
+                let color =
+                    egui::Color32::from_rgb((r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8);
+                let mode = match BlendMode::of_shader(shader) {
+                    Some(mode) => format!("{:?}", mode),
+                    None => "unknown, drawn as Alpha".to_owned(),
+                };
+                ui.colored_label(color, format!("shader {} ({})", shader, mode));
+            }
+        }
+    });
+
+    egui::Window::new("Filters").show(egui_context.ctx_mut(), |ui| {
+        let counts = match stream.counts() {
+            Some(counts) => counts,
+            None => {
+                ui.label("Counting the sprites of the map...");
+                return;
+            }
+        };
+        ui.label("Sprites of the whole map");
+        egui::CollapsingHeader::new("Layers").show(ui, |ui| {
+            facet_ui(ui, "layers", &mut settings.layers, &counts.layers);
         });
-        ui.horizontal(|ui| {
-            ui.checkbox(&mut settings.group_filter_on, "Group filter");
-            ui.add_enabled(
-                settings.group_filter_on,
-                egui::Slider::new(&mut settings.group, -1..=1),
+        egui::CollapsingHeader::new("Group keys").show(ui, |ui| {
+            facet_ui(
+                ui,
+                "group_keys",
+                &mut settings.group_keys,
+                &counts.group_keys,
             );
         });
+        egui::CollapsingHeader::new("Group ids").show(ui, |ui| {
+            facet_ui(ui, "group_ids", &mut settings.group_ids, &counts.group_ids);
+        });
+    });
+
+    egui::Window::new("Maps").show(egui_context.ctx_mut(), |ui| {
+        ui.label(format!("Viewing map {}", browser.current()));
+        if let Some(err) = browser.error() {
+            ui.colored_label(egui::Color32::RED, err);
+        }
+        egui::ScrollArea::vertical()
+            .max_height(300.)
+            .show(ui, |ui| {
+                let mut picked = None;
+                for &id in browser.maps() {
+                    let is_current = id == browser.current();
+                    if ui.selectable_label(is_current, id.to_string()).clicked() {
+                        picked = Some(id);
+                    }
+                }
+                if let Some(id) = picked {
+                    browser.request(id);
+                }
+            });
     });
 
     if settings.as_ref() != &copy {
         settings.updated = true;
     }
 }
+
+/// Lists the values of a facet with the number of sprites that have them.
+fn facet_ui<T: Ord + Copy + ToString>(
+    ui: &mut egui::Ui,
+    id: &str,
+    facet: &mut Facet<T>,
+    counts: &BTreeMap<T, usize>,
+) {
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut facet.invert, "Invert");
+        if ui.button("Reset").clicked() {
+            *facet = Facet::default();
+        }
+    });
+    egui::ScrollArea::vertical()
+        .id_source(id)
+        .max_height(200.)
+        .show(ui, |ui| {
+            egui::Grid::new(id).show(ui, |ui| {
+                for (value, count) in counts {
+                    toggle(ui, &mut facet.selected, *value, |ui, on| {
+                        ui.checkbox(on, value.to_string())
+                    });
+                    ui.label(count.to_string());
+                    toggle(ui, &mut facet.solo, *value, |ui, on| {
+                        ui.selectable_label(*on, "solo")
+                    });
+                    toggle(ui, &mut facet.muted, *value, |ui, on| {
+                        ui.selectable_label(*on, "mute")
+                    });
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+/// Adds or removes a value from a set when the widget is clicked.
+fn toggle<T: Ord>(
+    ui: &mut egui::Ui,
+    set: &mut BTreeSet<T>,
+    value: T,
+    widget: impl FnOnce(&mut egui::Ui, &mut bool) -> egui::Response,
+) {
+    let mut on = set.contains(&value);
+    if widget(ui, &mut on).clicked() {
+        if set.contains(&value) {
+            set.remove(&value);
+        } else {
+            set.insert(value);
+        }
+    }
+}
+
+pub fn inspector_system(
+    mut egui_context: ResMut<EguiContext>,
+    selection: Res<Selection>,
+    library: Res<ElementLibrary>,
+    sprites: Query<&SpriteSource>,
+) {
+    egui::Window::new("Inspector").show(egui_context.ctx_mut(), |ui| {
+        let sprite = match selection.entity.and_then(|entity| sprites.get(entity).ok()) {
+            Some(source) => &source.sprite,
+            None => {
+                ui.label("Click on a sprite to inspect it");
+                return;
+            }
+        };
+
+        ui.columns(2, |columns| {
+            columns[0].heading("Sprite");
+            egui::Grid::new("sprite").show(&mut columns[0], |ui| {
+                row(ui, "cell x", sprite.cell_x);
+                row(ui, "cell y", sprite.cell_y);
+                row(ui, "cell z", sprite.cell_z);
+                row(ui, "height", sprite.height);
+                row(ui, "altitude order", sprite.altitude_order);
+                row(ui, "layer", sprite.layer);
+                row(ui, "group key", sprite.group_key);
+                row(ui, "group id", sprite.group_id);
+                row(ui, "element id", sprite.element_id);
+                row(ui, "color", format!("{:.2?}", sprite.color().as_rgba_f32()));
+            });
+
+            columns[1].heading("Element");
+            let element = match library.get(sprite.element_id) {
+                Some(element) => element,
+                None => {
+                    columns[1].label("Not in the library");
+                    return;
+                }
+            };
+            egui::Grid::new("element").show(&mut columns[1], |ui| {
+                row(ui, "texture id", element.texture_id);
+                let origin = format!("{}, {}", element.origin_x, element.origin_y);
+                row(ui, "origin", origin);
+                row(ui, "slope", element.flags.slope());
+                row(ui, "flip", element.flags.is_flip());
+                row(ui, "move top", element.flags.is_move_top());
+                row(ui, "before mobile", element.flags.is_before_mobile());
+                row(ui, "walkable", element.flags.is_walkable());
+                row(ui, "shader", element.shader);
+                let blend = match BlendMode::of_shader(element.shader) {
+                    Some(mode) => format!("{:?}", mode),
+                    None => "unknown shader".to_owned(),
+                };
+                row(ui, "blend", blend);
+                row(ui, "visual height", element.visual_height);
+                let frames = element
+                    .animation
+                    .as_ref()
+                    .map_or(0, |frames| frames.frame_durations.len());
+                row(ui, "frames", frames);
+            });
+        });
+    });
+}
+
+fn row(ui: &mut egui::Ui, name: &str, value: impl ToString) {
+    ui.label(name);
+    ui.label(value.to_string());
+    ui.end_row();
+}
+
+pub fn cursor_readout_system(mut egui_context: ResMut<EguiContext>, hovered: Res<HoveredCell>) {
+    egui::Area::new("cursor")
+        .anchor(egui::Align2::LEFT_BOTTOM, [8., -8.])
+        .show(egui_context.ctx_mut(), |ui| match hovered.cell {
+            Some((cell, z)) => ui.label(format!("cell {}, {}, z {}", cell.x, cell.y, z)),
+            None => ui.label("no cell"),
+        });
+}
+
+pub fn path_tool_ui_system(mut egui_context: ResMut<EguiContext>, mut tool: ResMut<PathTool>) {
+    let copy = tool.clone();
+
+    egui::Window::new("Path").show(egui_context.ctx_mut(), |ui| {
+        ui.checkbox(&mut tool.picking, "Pick start and end cells");
+        ui.add(egui::Slider::new(&mut tool.max_step, 0..=20).text("Max height step"));
+        let cell = |cell: Option<IVec2>| match cell {
+            Some(cell) => format!("{}, {}", cell.x, cell.y),
+            None => "-".to_owned(),
+        };
+        ui.label(format!("Start: {}", cell(tool.start)));
+        ui.label(format!("End: {}", cell(tool.end)));
+        match (tool.end, tool.steps) {
+            (None, _) => {}
+            (Some(_), Some(steps)) => {
+                ui.label(format!("Reachable in {} steps", steps));
+            }
+            (Some(_), None) => {
+                ui.label("Unreachable");
+            }
+        }
+        if ui.button("Clear").clicked() {
+            tool.start = None;
+            tool.end = None;
+        }
+    });
+
+    if tool.as_ref() != &copy {
+        tool.updated = true;
+    }
+}
+
+/// Numbers the sprites on screen in the order they are drawn.
+pub fn render_order_system(
+    mut egui_context: ResMut<EguiContext>,
+    settings: Res<Settings>,
+    windows: Res<Windows>,
+    cameras: Query<&Transform, With<Camera>>,
+    sprites: Query<(&Transform, &Visibility), With<SpriteSource>>,
+) {
+    // past this the labels are unreadable anyway
+    const MAX_LABELS: usize = 2000;
+
+    if !settings.render_order {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let camera = cameras.single();
+
+    let mut order: Vec<Vec3> = sprites
+        .iter()
+        .filter(|(_, visibility)| visibility.is_visible)
+        .map(|(transform, _)| transform.translation)
+        .collect();
+    order.sort_by_key(|pos| FloatOrd(pos.z));
+
+    let painter = egui_context
+        .ctx_mut()
+        .layer_painter(egui::LayerId::background());
+    let labels = order.iter().enumerate().filter_map(|(idx, pos)| {
+        let pos = world_to_window(window, camera, pos.truncate());
+        let inside =
+            pos.x >= 0. && pos.y >= 0. && pos.x < window.width() && pos.y < window.height();
+        // egui's y axis points down
+        inside.then(|| (idx, egui::pos2(pos.x, window.height() - pos.y)))
+    });
+    for (idx, pos) in labels.take(MAX_LABELS) {
+        painter.text(
+            pos,
+            egui::Align2::LEFT_TOP,
+            idx.to_string(),
+            egui::FontId::monospace(10.),
+            egui::Color32::WHITE,
+        );
+    }
+}
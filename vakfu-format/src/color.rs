@@ -0,0 +1,44 @@
+/// An RGBA color with linear components, which may go above 1 to brighten what they tint.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgba_linear(1.0, 1.0, 1.0, 1.0);
+
+    #[inline]
+    pub const fn rgba_linear(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    #[inline]
+    pub fn as_linear_rgba_f32(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Components in sRGB space, the alpha stays linear.
+    pub fn as_rgba_f32(&self) -> [f32; 4] {
+        let [r, g, b] = [self.r, self.g, self.b].map(linear_to_srgb);
+        [r, g, b, self.a]
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl From<Color> for bevy::prelude::Color {
+    #[inline]
+    fn from(color: Color) -> Self {
+        bevy::prelude::Color::rgba_linear(color.r, color.g, color.b, color.a)
+    }
+}
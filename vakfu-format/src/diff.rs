@@ -0,0 +1,486 @@
+//! Changes between two versions of the element library or of a map, as shipped by two game
+//! patches.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+
+use glam::{IVec2, IVec3};
+use itertools::iproduct;
+
+use crate::map::element::{ElementLibrary, MapElement};
+use crate::map::sprite::MapSprite;
+use crate::map::Map;
+
+/// Elements added, removed or changed between two libraries, by element id.
+#[derive(Debug, Default)]
+pub struct LibraryDiff {
+    pub added: Vec<i32>,
+    pub removed: Vec<i32>,
+    /// Elements in both libraries along with the fields that differ.
+    pub changed: Vec<(i32, Vec<&'static str>)>,
+}
+
+impl LibraryDiff {
+    pub fn new(old: &ElementLibrary, new: &ElementLibrary) -> Self {
+        let mut diff = LibraryDiff::default();
+        for elem in old.elements() {
+            match new.get(elem.id) {
+                None => diff.removed.push(elem.id),
+                Some(other) => {
+                    let fields = changed_fields(elem, other);
+                    if !fields.is_empty() {
+                        diff.changed.push((elem.id, fields));
+                    }
+                }
+            }
+        }
+        for elem in new.elements() {
+            if old.get(elem.id).is_none() {
+                diff.added.push(elem.id);
+            }
+        }
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable_by_key(|(id, _)| *id);
+        diff
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn changed_fields(old: &MapElement, new: &MapElement) -> Vec<&'static str> {
+    let checks = [
+        (
+            "origin",
+            (old.origin_x, old.origin_y) != (new.origin_x, new.origin_y),
+        ),
+        (
+            "image_size",
+            (old.img_width, old.img_height) != (new.img_width, new.img_height),
+        ),
+        ("texture_id", old.texture_id != new.texture_id),
+        ("flags", old.flags.into_bytes() != new.flags.into_bytes()),
+        ("visual_height", old.visual_height != new.visual_height),
+        (
+            "visibility_mask",
+            old.visibility_mask != new.visibility_mask,
+        ),
+        ("export_mask", old.export_mask != new.export_mask),
+        ("shader", old.shader != new.shader),
+        ("animation", old.animation != new.animation),
+        ("ground_sound", old.ground_sound != new.ground_sound),
+    ];
+    checks
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| field)
+        .collect()
+}
+
+/// Identity of a sprite when matching it between two versions of a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpriteKey {
+    pub cell: (i32, i32),
+    pub layer: u8,
+    pub element_id: i32,
+    pub group_key: i32,
+}
+
+impl SpriteKey {
+    #[inline]
+    pub fn of(sprite: &MapSprite) -> Self {
+        Self {
+            cell: (sprite.cell_x, sprite.cell_y),
+            layer: sprite.layer,
+            element_id: sprite.element_id,
+            group_key: sprite.group_key,
+        }
+    }
+
+    #[inline]
+    pub fn cell(&self) -> IVec2 {
+        IVec2::new(self.cell.0, self.cell.1)
+    }
+}
+
+/// A sprite of one version of a map, along with the position of its chunk and its index in
+/// the sprites of the chunk, which tell apart stacked copies of a sprite.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffSprite<'a> {
+    pub sprite: &'a MapSprite,
+    pub chunk: (i32, i32),
+    pub index: usize,
+}
+
+impl<'a> DiffSprite<'a> {
+    #[inline]
+    pub fn id(&self) -> ((i32, i32), usize) {
+        (self.chunk, self.index)
+    }
+}
+
+impl<'a> Deref for DiffSprite<'a> {
+    type Target = MapSprite;
+
+    #[inline]
+    fn deref(&self) -> &MapSprite {
+        self.sprite
+    }
+}
+
+/// The same sprite in the old and the new version of a map.
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteMatch<'a> {
+    pub old: DiffSprite<'a>,
+    pub new: DiffSprite<'a>,
+}
+
+impl<'a> SpriteMatch<'a> {
+    #[inline]
+    pub fn is_moved(&self) -> bool {
+        position(&self.old) != position(&self.new) || self.old.height != self.new.height
+    }
+
+    #[inline]
+    pub fn is_tint_changed(&self) -> bool {
+        self.old.colors != self.new.colors
+    }
+}
+
+/// Cell and elevation of a sprite.
+#[inline]
+pub fn position(sprite: &MapSprite) -> IVec3 {
+    IVec3::new(sprite.cell_x, sprite.cell_y, sprite.cell_z.into())
+}
+
+/// Sprites added, removed and changed between two versions of a map.
+/// Sprites are matched by `SpriteKey` first, ones left over in both versions that only differ
+/// by their cell are matched as moved when they are at most `MOVE_RADIUS` cells apart.
+#[derive(Debug, Default)]
+pub struct MapDiff<'a> {
+    pub added: Vec<DiffSprite<'a>>,
+    pub removed: Vec<DiffSprite<'a>>,
+    pub moved: Vec<SpriteMatch<'a>>,
+    pub tint_changed: Vec<SpriteMatch<'a>>,
+    /// Sprites whose element is drawn from another texture, with the old and new texture ids.
+    pub texture_changed: Vec<(SpriteMatch<'a>, i32, i32)>,
+    pub unchanged: usize,
+}
+
+impl<'a> MapDiff<'a> {
+    /// Largest distance in cells between the old and new position of a moved sprite, sprites
+    /// that are farther apart are reported as removed and added.
+    pub const MOVE_RADIUS: i32 = 4;
+
+    pub fn new(
+        old: &'a Map,
+        old_library: &ElementLibrary,
+        new: &'a Map,
+        new_library: &ElementLibrary,
+    ) -> Self {
+        let mut diff = MapDiff::default();
+        let mut old_sprites = group_sprites(old, SpriteKey::of);
+        let mut new_sprites = group_sprites(new, SpriteKey::of);
+
+        let mut matches = vec![];
+        pair_off(&mut old_sprites, &mut new_sprites, &mut matches);
+
+        // what's left only differs by cell, if anything
+        let by_element = |key: &SpriteKey| (key.layer, key.element_id, key.group_key);
+        let mut removed = regroup(old_sprites, by_element);
+        let mut added = regroup(new_sprites, by_element);
+        pair_nearby(&mut removed, &mut added, &mut matches);
+        diff.removed = removed.into_values().flatten().collect();
+        diff.added = added.into_values().flatten().collect();
+
+        for pair in matches {
+            let old_texture = old_library.get(pair.old.element_id).map(|e| e.texture_id);
+            let new_texture = new_library.get(pair.new.element_id).map(|e| e.texture_id);
+            let mut changed = false;
+            if pair.is_moved() {
+                diff.moved.push(pair);
+                changed = true;
+            }
+            if pair.is_tint_changed() {
+                diff.tint_changed.push(pair);
+                changed = true;
+            }
+            if let (Some(old), Some(new)) = (old_texture, new_texture) {
+                if old != new {
+                    diff.texture_changed.push((pair, old, new));
+                    changed = true;
+                }
+            }
+            if !changed {
+                diff.unchanged += 1;
+            }
+        }
+        diff
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.tint_changed.is_empty()
+            && self.texture_changed.is_empty()
+    }
+}
+
+/// Sprites of a map by key, each group ordered from the lowest sprite up.
+fn group_sprites<'a, K: Ord>(
+    map: &'a Map,
+    key: impl Fn(&MapSprite) -> K,
+) -> BTreeMap<K, Vec<DiffSprite<'a>>> {
+    let mut groups: BTreeMap<K, Vec<DiffSprite>> = BTreeMap::new();
+    for chunk in map.chunks() {
+        for (index, sprite) in chunk.sprites.iter().enumerate() {
+            groups.entry(key(sprite)).or_default().push(DiffSprite {
+                sprite,
+                chunk: (chunk.map_x, chunk.map_y),
+                index,
+            });
+        }
+    }
+    for sprites in groups.values_mut() {
+        sprites.sort_by_key(|sprite| (sprite.cell_z, sprite.height, sprite.altitude_order));
+    }
+    groups
+}
+
+/// Moves the sprites found under the same key in both versions to `matches`, stacked copies
+/// of a sprite are paired from the bottom up.
+fn pair_off<'a, K: Ord>(
+    old: &mut BTreeMap<K, Vec<DiffSprite<'a>>>,
+    new: &mut BTreeMap<K, Vec<DiffSprite<'a>>>,
+    matches: &mut Vec<SpriteMatch<'a>>,
+) {
+    for (key, olds) in old {
+        if let Some(news) = new.get_mut(key) {
+            let count = olds.len().min(news.len());
+            let pairs = olds.drain(..count).zip(news.drain(..count));
+            matches.extend(pairs.map(|(old, new)| SpriteMatch { old, new }));
+        }
+    }
+}
+
+/// Moves the sprites found under the same key in both versions to `matches` when they are
+/// close enough to be the same sprite moved, the closest ones are paired first.
+fn pair_nearby<'a, K: Ord>(
+    old: &mut BTreeMap<K, Vec<DiffSprite<'a>>>,
+    new: &mut BTreeMap<K, Vec<DiffSprite<'a>>>,
+    matches: &mut Vec<SpriteMatch<'a>>,
+) {
+    const RADIUS: i32 = MapDiff::MOVE_RADIUS;
+
+    for (key, olds) in old {
+        let news = match new.get_mut(key) {
+            Some(news) => news,
+            None => continue,
+        };
+        let mut by_cell: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (idx, sprite) in news.iter().enumerate() {
+            by_cell
+                .entry((sprite.cell_x, sprite.cell_y))
+                .or_default()
+                .push(idx);
+        }
+
+        let mut candidates = vec![];
+        for (old_idx, sprite) in olds.iter().enumerate() {
+            for (dx, dy) in iproduct!(-RADIUS..=RADIUS, -RADIUS..=RADIUS) {
+                let cell = (sprite.cell_x + dx, sprite.cell_y + dy);
+                for &new_idx in by_cell.get(&cell).into_iter().flatten() {
+                    let other = news[new_idx];
+                    let dz = (sprite.cell_z as i32 - other.cell_z as i32).abs();
+                    candidates.push((dx.abs().max(dy.abs()), dz, old_idx, new_idx));
+                }
+            }
+        }
+        candidates.sort_unstable();
+
+        let mut old_paired = vec![false; olds.len()];
+        let mut new_paired = vec![false; news.len()];
+        for (_, _, old_idx, new_idx) in candidates {
+            if !old_paired[old_idx] && !new_paired[new_idx] {
+                old_paired[old_idx] = true;
+                new_paired[new_idx] = true;
+                matches.push(SpriteMatch {
+                    old: olds[old_idx],
+                    new: news[new_idx],
+                });
+            }
+        }
+        let mut paired = old_paired.into_iter();
+        olds.retain(|_| !paired.next().unwrap_or(false));
+        let mut paired = new_paired.into_iter();
+        news.retain(|_| !paired.next().unwrap_or(false));
+    }
+}
+
+fn regroup<'a, K: Ord, L: Ord>(
+    groups: BTreeMap<K, Vec<DiffSprite<'a>>>,
+    key: impl Fn(&K) -> L,
+) -> BTreeMap<L, Vec<DiffSprite<'a>>> {
+    let mut regrouped: BTreeMap<L, Vec<DiffSprite>> = BTreeMap::new();
+    for (k, sprites) in groups {
+        regrouped.entry(key(&k)).or_default().extend(sprites);
+    }
+    regrouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::chunk::{ChunkRect, MapChunk};
+    use crate::map::element::ElementFlags;
+    use crate::map::sprite::Tint;
+    use crate::map::version::FormatVersion;
+
+    fn library(elements: &[(i32, i32)]) -> ElementLibrary {
+        let elements = elements
+            .iter()
+            .map(|&(id, texture_id)| MapElement {
+                id,
+                origin_x: 0,
+                origin_y: 0,
+                img_width: 64,
+                img_height: 32,
+                texture_id,
+                flags: ElementFlags::new(),
+                visual_height: 0,
+                visibility_mask: 0,
+                export_mask: 0,
+                shader: 0,
+                animation: None,
+                ground_sound: 0,
+            })
+            .collect();
+        ElementLibrary::new(FormatVersion::Compact, elements)
+    }
+
+    fn sprite(cell: (i32, i32), element_id: i32) -> MapSprite {
+        MapSprite {
+            cell_x: cell.0,
+            cell_y: cell.1,
+            layer: 1,
+            element_id,
+            ..Default::default()
+        }
+    }
+
+    fn map(sprites: Vec<MapSprite>) -> Map {
+        let chunk = MapChunk {
+            map_x: 0,
+            map_y: 0,
+            min_x: 0,
+            min_y: 0,
+            min_z: 0,
+            max_x: 18,
+            max_y: 18,
+            max_z: 0,
+            rects: vec![ChunkRect {
+                min_x: 0,
+                max_x: 18,
+                min_y: 0,
+                max_y: 18,
+            }],
+            sprites,
+        };
+        Map::new(FormatVersion::Compact, vec![("0_0".to_owned(), chunk)])
+    }
+
+    fn cells(sprites: &[DiffSprite]) -> Vec<(i32, i32)> {
+        sprites.iter().map(|s| (s.cell_x, s.cell_y)).collect()
+    }
+
+    #[test]
+    fn unchanged() {
+        let lib = library(&[(1, 10), (2, 20)]);
+        let sprites = vec![sprite((0, 0), 1), sprite((0, 0), 1), sprite((3, 4), 2)];
+        let (old, new) = (map(sprites.clone()), map(sprites));
+        let diff = MapDiff::new(&old, &lib, &new, &lib);
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged, 3);
+    }
+
+    #[test]
+    fn added_and_removed() {
+        let lib = library(&[(1, 10), (2, 20), (3, 30)]);
+        let old = map(vec![sprite((0, 0), 1), sprite((2, 2), 2)]);
+        let new = map(vec![sprite((0, 0), 1), sprite((5, 5), 3)]);
+        let diff = MapDiff::new(&old, &lib, &new, &lib);
+        assert_eq!(cells(&diff.removed), [(2, 2)]);
+        assert_eq!(cells(&diff.added), [(5, 5)]);
+        assert_eq!(diff.removed[0].id(), ((0, 0), 1));
+        assert_eq!(diff.added[0].id(), ((0, 0), 1));
+        assert!(diff.moved.is_empty());
+        assert_eq!(diff.unchanged, 1);
+    }
+
+    #[test]
+    fn moved() {
+        let lib = library(&[(1, 10)]);
+        let old = map(vec![sprite((2, 2), 1), sprite((9, 9), 1)]);
+        let new = map(vec![sprite((2, 3), 1), sprite((9, 8), 1)]);
+        let diff = MapDiff::new(&old, &lib, &new, &lib);
+        let moves = diff
+            .moved
+            .iter()
+            .map(|pair| cells(&[pair.old, pair.new]))
+            .collect::<Vec<_>>();
+        assert_eq!(moves, [[(2, 2), (2, 3)], [(9, 9), (9, 8)]]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn far_apart_not_moved() {
+        let lib = library(&[(1, 10)]);
+        let old = map(vec![sprite((0, 0), 1)]);
+        let new = map(vec![sprite((17, 17), 1)]);
+        let diff = MapDiff::new(&old, &lib, &new, &lib);
+        assert!(diff.moved.is_empty());
+        assert_eq!(cells(&diff.removed), [(0, 0)]);
+        assert_eq!(cells(&diff.added), [(17, 17)]);
+    }
+
+    #[test]
+    fn tint_changed() {
+        let lib = library(&[(1, 10)]);
+        let mut tinted = sprite((1, 1), 1);
+        tinted.colors.flags = 0x08;
+        tinted.colors.tint = Some(Tint {
+            rgb: None,
+            alpha: Some(-10),
+        });
+        let old = map(vec![sprite((1, 1), 1)]);
+        let new = map(vec![tinted]);
+        let diff = MapDiff::new(&old, &lib, &new, &lib);
+        assert_eq!(diff.tint_changed.len(), 1);
+        assert!(diff.moved.is_empty() && diff.texture_changed.is_empty());
+        assert_eq!(diff.unchanged, 0);
+    }
+
+    #[test]
+    fn texture_changed() {
+        let old_lib = library(&[(1, 10), (2, 20)]);
+        let new_lib = library(&[(1, 10), (2, 21)]);
+        let sprites = vec![sprite((0, 0), 1), sprite((1, 1), 2)];
+        let (old, new) = (map(sprites.clone()), map(sprites));
+        let diff = MapDiff::new(&old, &old_lib, &new, &new_lib);
+        let changes = diff
+            .texture_changed
+            .iter()
+            .map(|(pair, old, new)| (pair.new.element_id, *old, *new))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [(2, 20, 21)]);
+        assert_eq!(diff.unchanged, 1);
+
+        let libraries = LibraryDiff::new(&old_lib, &new_lib);
+        assert_eq!(libraries.changed, [(2, vec!["texture_id"])]);
+    }
+}
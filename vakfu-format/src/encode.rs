@@ -0,0 +1,22 @@
+use byte::TryWrite;
+
+/// Writes a value into a new buffer, growing it until the value fits.
+pub fn encode<T, C>(value: T, ctx: C) -> byte::Result<Vec<u8>>
+where
+    T: TryWrite<C> + Copy,
+    C: Copy,
+{
+    let mut buffer = vec![0; 4096];
+    loop {
+        match value.try_write(&mut buffer, ctx) {
+            Ok(size) => {
+                buffer.truncate(size);
+                return Ok(buffer);
+            }
+            Err(byte::Error::Incomplete | byte::Error::BadOffset(_)) => {
+                buffer.resize(buffer.len() * 2, 0);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::{self, Display};
+use std::io;
+
+use thiserror::Error;
+
+/// Parsed value along with the number of bytes it spans.
+pub type ParseResult<T> = Result<(T, usize), FormatError>;
+
+/// Position in the game files where a parse error happened.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub entry: Option<String>,
+    pub chunk: Option<(i32, i32)>,
+    pub sprite: Option<usize>,
+    pub element: Option<i32>,
+    pub field: &'static str,
+    pub offset: usize,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(entry) = &self.entry {
+            write!(f, "{} > ", entry)?;
+        }
+        if let Some((x, y)) = self.chunk {
+            write!(f, "chunk ({}, {}) > ", x, y)?;
+        }
+        if let Some(idx) = self.sprite {
+            write!(f, "sprite #{} > ", idx)?;
+        }
+        if let Some(id) = self.element {
+            write!(f, "element {} > ", id)?;
+        }
+        write!(f, "{} at byte {}", self.field, self.offset)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("unexpected end of data reading {0}")]
+    Incomplete(Location),
+    #[error("bad offset reading {0}")]
+    BadOffset(Location),
+    #[error("invalid {0}: {1}")]
+    BadInput(Location, &'static str),
+    #[error("{} > unknown format version", .0.as_deref().unwrap_or("data"))]
+    UnknownVersion(Option<String>),
+    #[error("{0} > write error: {1:?}")]
+    Write(String, byte::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+}
+
+impl FormatError {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            FormatError::Incomplete(loc)
+            | FormatError::BadOffset(loc)
+            | FormatError::BadInput(loc, _) => Some(loc),
+            _ => None,
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            FormatError::Incomplete(loc)
+            | FormatError::BadOffset(loc)
+            | FormatError::BadInput(loc, _) => Some(loc),
+            _ => None,
+        }
+    }
+
+    fn with(mut self, f: impl FnOnce(&mut Location)) -> Self {
+        if let Some(loc) = self.location_mut() {
+            f(loc);
+        }
+        self
+    }
+
+    /// Shifts the offset of an error raised while parsing a slice that starts at `base`.
+    pub fn offset_by(self, base: usize) -> Self {
+        self.with(|loc| loc.offset += base)
+    }
+
+    pub fn in_entry(self, entry: &str) -> Self {
+        match self {
+            FormatError::UnknownVersion(_) => FormatError::UnknownVersion(Some(entry.to_owned())),
+            err => err.with(|loc| loc.entry = Some(entry.to_owned())),
+        }
+    }
+
+    pub fn in_chunk(self, map_x: i32, map_y: i32) -> Self {
+        self.with(|loc| loc.chunk = Some((map_x, map_y)))
+    }
+
+    pub fn in_sprite(self, idx: usize) -> Self {
+        self.with(|loc| loc.sprite = Some(idx))
+    }
+
+    pub fn in_element(self, id: i32) -> Self {
+        self.with(|loc| loc.element = Some(id))
+    }
+}
+
+impl From<FormatError> for byte::Error {
+    fn from(err: FormatError) -> Self {
+        match err {
+            FormatError::Incomplete(_) => byte::Error::Incomplete,
+            FormatError::BadOffset(loc) => byte::Error::BadOffset(loc.offset),
+            FormatError::BadInput(_, err) => byte::Error::BadInput { err },
+            FormatError::UnknownVersion(_) => byte::Error::BadInput {
+                err: "unknown format version",
+            },
+            FormatError::Write(_, err) => err,
+            FormatError::Io(_) | FormatError::Zip(_) => byte::Error::BadInput { err: "I/O error" },
+        }
+    }
+}
+
+/// Attaches the field being read to errors of the `byte` crate.
+pub trait FieldContext<T> {
+    fn field(self, field: &'static str, offset: usize) -> Result<T, FormatError>;
+}
+
+impl<T> FieldContext<T> for byte::Result<T> {
+    fn field(self, field: &'static str, offset: usize) -> Result<T, FormatError> {
+        self.map_err(|err| {
+            let loc = Location {
+                field,
+                offset,
+                ..Default::default()
+            };
+            match err {
+                byte::Error::Incomplete => FormatError::Incomplete(loc),
+                byte::Error::BadOffset(_) => FormatError::BadOffset(loc),
+                byte::Error::BadInput { err } => FormatError::BadInput(loc, err),
+            }
+        })
+    }
+}
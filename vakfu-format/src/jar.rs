@@ -0,0 +1,86 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A jar archive of the game files, readable from several threads.
+pub struct Jar {
+    archive: Mutex<zip::ZipArchive<BufReader<File>>>,
+}
+
+impl Jar {
+    pub fn open<P: AsRef<Path>>(path: P) -> zip::result::ZipResult<Self> {
+        let archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+        let jar = Jar {
+            archive: Mutex::new(archive),
+        };
+        Ok(jar)
+    }
+
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut entry = archive
+            .by_name(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, name.to_owned()))?;
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Reads at most `len` bytes from the start of an entry.
+    pub fn read_prefix(&self, name: &str, len: usize) -> io::Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let entry = archive
+            .by_name(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, name.to_owned()))?;
+        let mut bytes = Vec::with_capacity(len);
+        entry.take(len as u64).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let archive = self.archive.lock().unwrap();
+        archive.file_names().map(str::to_owned).collect()
+    }
+
+    /// Names of the entries along with them as relative paths, entries whose name would
+    /// escape the directory they are extracted to are left out.
+    pub fn enclosed_names(&self) -> Vec<(String, PathBuf)> {
+        let mut archive = self.archive.lock().unwrap();
+        (0..archive.len())
+            .filter_map(|i| {
+                let entry = archive.by_index(i).ok()?;
+                let path = entry.enclosed_name()?.to_owned();
+                Some((entry.name().to_owned(), path))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn enclosed_names() {
+        let path = std::env::temp_dir().join("vakfu-enclosed-names.jar");
+        let mut archive = zip::ZipWriter::new(File::create(&path).unwrap());
+        for name in ["gfx/1.tgam", "../2.tgam", "gfx/../../3.tgam", "/4.tgam"] {
+            archive.start_file(name, Default::default()).unwrap();
+            archive.write_all(b"tgam").unwrap();
+        }
+        archive.finish().unwrap();
+        drop(archive);
+
+        let jar = Jar::open(&path).unwrap();
+        assert_eq!(jar.names().len(), 4);
+        assert_eq!(
+            jar.enclosed_names(),
+            [("gfx/1.tgam".to_owned(), PathBuf::from("gfx/1.tgam"))]
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
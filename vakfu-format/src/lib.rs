@@ -0,0 +1,11 @@
+//! Readers and writers for the map, element and texture formats of Wakfu.
+//! Nothing here depends on Bevy, the `bevy` feature adds conversions to its color and rect types.
+
+pub mod color;
+pub mod diff;
+mod encode;
+pub mod error;
+pub mod jar;
+pub mod map;
+pub mod rect;
+pub mod tgam;
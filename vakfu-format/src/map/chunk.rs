@@ -0,0 +1,417 @@
+use std::ops::Range;
+
+use byte::{BytesExt, TryRead, TryWrite};
+use itertools::Itertools;
+
+use super::sprite::{MapSprite, SpriteColors};
+use super::version::FormatVersion;
+use crate::error::{FieldContext, FormatError, ParseResult};
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapChunk {
+    pub map_x: i32,
+    pub map_y: i32,
+    pub min_x: i32,
+    pub min_y: i32,
+    pub min_z: i16,
+    pub max_x: i32,
+    pub max_y: i32,
+    pub max_z: i16,
+    pub rects: Vec<ChunkRect>,
+    pub sprites: Vec<MapSprite>,
+}
+
+/// Position and bounds of a chunk, stored at the start of its entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub map_x: i32,
+    pub map_y: i32,
+    pub min_x: i32,
+    pub min_y: i32,
+    pub min_z: i16,
+    pub max_x: i32,
+    pub max_y: i32,
+    pub max_z: i16,
+}
+
+impl ChunkHeader {
+    pub const SIZE: usize = 28;
+
+    pub fn parse(bytes: &[u8]) -> ParseResult<Self> {
+        let offset = &mut 0;
+
+        let min_x: i32 = bytes.read(offset).field("min_x", *offset)?;
+        let min_y: i32 = bytes.read(offset).field("min_y", *offset)?;
+        let min_z: i16 = bytes.read(offset).field("min_z", *offset)?;
+        let max_x: i32 = bytes.read(offset).field("max_x", *offset)?;
+        let max_y: i32 = bytes.read(offset).field("max_y", *offset)?;
+        let max_z: i16 = bytes.read(offset).field("max_z", *offset)?;
+
+        let map_x: i32 = bytes.read(offset).field("map_x", *offset)?;
+        let map_y: i32 = bytes.read(offset).field("map_y", *offset)?;
+
+        let header = ChunkHeader {
+            map_x,
+            map_y,
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+        };
+        Ok((header, *offset))
+    }
+}
+
+impl TryWrite for &ChunkHeader {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        bytes.write(offset, self.min_x)?;
+        bytes.write(offset, self.min_y)?;
+        bytes.write(offset, self.min_z)?;
+        bytes.write(offset, self.max_x)?;
+        bytes.write(offset, self.max_y)?;
+        bytes.write(offset, self.max_z)?;
+
+        bytes.write(offset, self.map_x)?;
+        bytes.write(offset, self.map_y)?;
+        Ok(*offset)
+    }
+}
+
+/// Block of cells listed in a chunk, relative to the chunk origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChunkRect {
+    pub min_x: u8,
+    pub max_x: u8,
+    pub min_y: u8,
+    pub max_y: u8,
+}
+
+impl ChunkRect {
+    #[inline]
+    pub fn cells_x(&self, map_x: i32) -> Range<i32> {
+        map_x + self.min_x as i32..map_x + self.max_x as i32
+    }
+
+    #[inline]
+    pub fn cells_y(&self, map_y: i32) -> Range<i32> {
+        map_y + self.min_y as i32..map_y + self.max_y as i32
+    }
+}
+
+impl MapChunk {
+    pub fn parse(bytes: &[u8], version: FormatVersion) -> ParseResult<Self> {
+        let (header, mut offset) = ChunkHeader::parse(bytes)?;
+        let offset = &mut offset;
+
+        let ChunkHeader {
+            map_x,
+            map_y,
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+        } = header;
+        let rect_count: u16 = bytes
+            .read(offset)
+            .field("rects", *offset)
+            .map_err(|err| err.in_chunk(map_x, map_y))?;
+        let mut sprites: Vec<MapSprite> = Vec::with_capacity(rect_count as usize * 2);
+        let mut rects = Vec::with_capacity(rect_count as usize);
+
+        for _ in 0..rect_count {
+            let rect = read_rect(bytes, offset).map_err(|err| err.in_chunk(map_x, map_y))?;
+            rects.push(rect);
+
+            for cell_x in rect.cells_x(map_x) {
+                for cell_y in rect.cells_y(map_y) {
+                    let count: u8 = bytes
+                        .read(offset)
+                        .field("count", *offset)
+                        .map_err(|err| err.in_chunk(map_x, map_y))?;
+                    for _ in 0..count {
+                        let sprite = read_sprite(bytes, offset, version, cell_x, cell_y)
+                            .map_err(|err| err.in_sprite(sprites.len()).in_chunk(map_x, map_y))?;
+                        sprites.push(sprite);
+                    }
+                }
+            }
+        }
+        let chunk = MapChunk {
+            map_x,
+            map_y,
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+            rects,
+            sprites,
+        };
+        Ok((chunk, *offset))
+    }
+
+    /// Rank of each sprite in the drawing order of the sprites of its cell.
+    pub fn cell_ranks(&self) -> Vec<usize> {
+        let mut ranks = vec![0; self.sprites.len()];
+        let sorted = self
+            .sprites
+            .iter()
+            .enumerate()
+            .sorted_by_key(|(idx, sprite)| sprite.sort_key(*idx));
+        // the sprites of a cell come in a row once sorted
+        let mut previous = None;
+        let mut rank = 0;
+        for (idx, sprite) in sorted {
+            let cell = (sprite.cell_x, sprite.cell_y);
+            rank = if previous == Some(cell) { rank + 1 } else { 0 };
+            previous = Some(cell);
+            ranks[idx] = rank;
+        }
+        ranks
+    }
+
+    pub fn header(&self) -> ChunkHeader {
+        ChunkHeader {
+            map_x: self.map_x,
+            map_y: self.map_y,
+            min_x: self.min_x,
+            min_y: self.min_y,
+            min_z: self.min_z,
+            max_x: self.max_x,
+            max_y: self.max_y,
+            max_z: self.max_z,
+        }
+    }
+}
+
+impl<'a> TryRead<'a, FormatVersion> for MapChunk {
+    fn try_read(bytes: &'a [u8], version: FormatVersion) -> byte::Result<(Self, usize)> {
+        Ok(MapChunk::parse(bytes, version)?)
+    }
+}
+
+impl TryWrite<FormatVersion> for &MapChunk {
+    fn try_write(self, bytes: &mut [u8], version: FormatVersion) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        bytes.write(offset, &self.header())?;
+        bytes.write(offset, self.rects.len() as u16)?;
+
+        // sprites are stored grouped by cell, in the order the rects list the cells
+        let mut sprites = self.sprites.iter().peekable();
+        for rect in &self.rects {
+            bytes.write(offset, rect.min_x)?;
+            bytes.write(offset, rect.max_x)?;
+            bytes.write(offset, rect.min_y)?;
+            bytes.write(offset, rect.max_y)?;
+
+            for cell_x in rect.cells_x(self.map_x) {
+                for cell_y in rect.cells_y(self.map_y) {
+                    let cell = sprites
+                        .peeking_take_while(|s| s.cell_x == cell_x && s.cell_y == cell_y)
+                        .take(u8::MAX as usize)
+                        .collect_vec();
+                    bytes.write(offset, cell.len() as u8)?;
+                    for sprite in cell {
+                        write_sprite(bytes, offset, sprite, version)?;
+                    }
+                }
+            }
+        }
+        if sprites.next().is_some() {
+            let err = "Sprite outside of the chunk rects";
+            return Err(byte::Error::BadInput { err });
+        }
+        Ok(*offset)
+    }
+}
+
+fn read_rect(bytes: &[u8], offset: &mut usize) -> Result<ChunkRect, FormatError> {
+    let rect = ChunkRect {
+        min_x: bytes.read(offset).field("rect_min_x", *offset)?,
+        max_x: bytes.read(offset).field("rect_max_x", *offset)?,
+        min_y: bytes.read(offset).field("rect_min_y", *offset)?,
+        max_y: bytes.read(offset).field("rect_max_y", *offset)?,
+    };
+    Ok(rect)
+}
+
+fn read_sprite(
+    bytes: &[u8],
+    offset: &mut usize,
+    version: FormatVersion,
+    cell_x: i32,
+    cell_y: i32,
+) -> Result<MapSprite, FormatError> {
+    let typ: u8 = bytes.read(offset).field("type", *offset)?;
+    let cell_z = bytes.read(offset).field("cell_z", *offset)?;
+    let height = bytes.read(offset).field("height", *offset)?;
+    let altitude_order = bytes.read(offset).field("altitude_order", *offset)?;
+    let tag = if version.has_sprite_tag() {
+        bytes.read(offset).field("tag", *offset)?
+    } else {
+        0
+    };
+    let group_key: i32 = bytes.read(offset).field("group_key", *offset)?;
+    let layer: u8 = bytes.read(offset).field("layer", *offset)?;
+    let group_id: i32 = bytes.read(offset).field("group_id", *offset)?;
+    let occluder: bool = bytes.read(offset).field("occluder", *offset)?;
+    let element_id = bytes.read(offset).field("element_id", *offset)?;
+    let colors = if bytes.len() == *offset {
+        // some chunks end before the color table of their last sprite
+        SpriteColors::empty(typ)
+    } else {
+        bytes.read_with(offset, typ).field("colors", *offset)?
+    };
+    let sprite = MapSprite {
+        cell_x,
+        cell_y,
+        cell_z,
+        height,
+        altitude_order,
+        tag,
+        element_id,
+        group_key,
+        group_id,
+        layer,
+        occluder,
+        colors,
+    };
+    Ok(sprite)
+}
+
+fn write_sprite(
+    bytes: &mut [u8],
+    offset: &mut usize,
+    sprite: &MapSprite,
+    version: FormatVersion,
+) -> byte::Result<()> {
+    bytes.write(offset, sprite.colors.flags)?;
+    bytes.write(offset, sprite.cell_z)?;
+    bytes.write(offset, sprite.height)?;
+    bytes.write(offset, sprite.altitude_order)?;
+    if version.has_sprite_tag() {
+        bytes.write(offset, sprite.tag)?;
+    }
+    bytes.write(offset, sprite.group_key)?;
+    bytes.write(offset, sprite.layer)?;
+    bytes.write(offset, sprite.group_id)?;
+    bytes.write(offset, sprite.occluder as u8)?;
+    bytes.write(offset, sprite.element_id)?;
+    bytes.write(offset, &sprite.colors)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::encode::encode;
+
+    fn sprite_bytes(bytes: &mut Vec<u8>, version: FormatVersion, flags: u8) {
+        bytes.push(flags);
+        bytes.extend(2i16.to_le_bytes());
+        bytes.extend([4, 1]);
+        if version.has_sprite_tag() {
+            bytes.push(9);
+        }
+        bytes.extend(77i32.to_le_bytes());
+        bytes.push(3);
+        bytes.extend(5i32.to_le_bytes());
+        bytes.push(1);
+        bytes.extend(1234i32.to_le_bytes());
+        let colors = [(0x02, 3), (0x08, 1), (0x01, 3), (0x04, 3)]
+            .iter()
+            .filter(|(flag, _)| flags & flag != 0)
+            .map(|(flag, size)| {
+                if flags & 0x10 != 0 && *flag & 0x0A != 0 {
+                    size * 2
+                } else {
+                    *size
+                }
+            })
+            .sum::<u8>();
+        bytes.extend(1..=colors);
+    }
+
+    /// A chunk with a rect of four cells, holding two, none, one and one sprites.
+    pub(crate) fn chunk_bytes(version: FormatVersion) -> Vec<u8> {
+        let mut bytes = vec![];
+        for value in [0i32, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(0i16.to_le_bytes());
+        for value in [17i32, 17] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(3i16.to_le_bytes());
+        for value in [18i32, 36] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(1u16.to_le_bytes());
+        bytes.extend([0, 2, 1, 3]);
+        bytes.push(2);
+        sprite_bytes(&mut bytes, version, 0x02);
+        sprite_bytes(&mut bytes, version, 0x1A);
+        bytes.push(0);
+        bytes.push(1);
+        sprite_bytes(&mut bytes, version, 0x0F);
+        bytes.push(1);
+        sprite_bytes(&mut bytes, version, 0x00);
+        bytes
+    }
+
+    #[test]
+    fn header_size() {
+        let bytes = chunk_bytes(FormatVersion::Compact);
+        let (_, size) = ChunkHeader::parse(&bytes).unwrap();
+        assert_eq!(size, ChunkHeader::SIZE);
+    }
+
+    #[test]
+    fn cell_ranks() {
+        let (chunk, _) =
+            MapChunk::parse(&chunk_bytes(FormatVersion::Compact), FormatVersion::Compact).unwrap();
+        assert_eq!(chunk.cell_ranks(), [0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        for version in FormatVersion::ALL {
+            let bytes = chunk_bytes(version);
+            let (chunk, size) = MapChunk::parse(&bytes, version).unwrap();
+            assert_eq!(size, bytes.len());
+            assert_eq!(chunk.sprites.len(), 4);
+            assert_eq!((chunk.sprites[3].cell_x, chunk.sprites[3].cell_y), (19, 38));
+            assert_eq!(encode(&chunk, version).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn truncated_color_table() {
+        for version in FormatVersion::ALL {
+            // the last sprite has a full color table but the chunk ends before it
+            let mut bytes = chunk_bytes(version);
+            let sprite_size = if version.has_sprite_tag() { 20 } else { 19 };
+            let flags = bytes.len() - sprite_size;
+            bytes[flags] = 0x0F;
+
+            let (chunk, size) = MapChunk::parse(&bytes, version).unwrap();
+            assert_eq!(size, bytes.len());
+            let sprite = &chunk.sprites[3];
+            assert_eq!(sprite.colors, SpriteColors::empty(0x0F));
+            assert_eq!(sprite.element_id, 1234);
+            assert_eq!(sprite.color(), Color::WHITE);
+            assert_eq!(encode(&chunk, version).unwrap(), bytes);
+        }
+    }
+}
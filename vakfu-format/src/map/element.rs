@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use byte::{BytesExt, TryRead, TryWrite};
+use glam::Vec2;
+use modular_bitfield::prelude::*;
+
+use super::frames::Frames;
+use super::version::FormatVersion;
+use crate::encode::encode;
+use crate::error::{FieldContext, FormatError, ParseResult};
+use crate::rect::Rect;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapElement {
+    pub id: i32,
+    pub origin_x: i16,
+    pub origin_y: i16,
+    pub img_width: u16,
+    pub img_height: u16,
+    pub texture_id: i32,
+    pub flags: ElementFlags,
+    pub visual_height: u8,
+    pub visibility_mask: u8,
+    pub export_mask: u8,
+    pub shader: u8,
+    pub animation: Option<Frames>,
+    pub ground_sound: u8,
+}
+
+impl MapElement {
+    #[inline]
+    pub fn image_size(&self) -> Vec2 {
+        Vec2::new(self.img_width as f32, self.img_height as f32)
+    }
+
+    pub fn size(&self) -> Vec2 {
+        match self.animation {
+            None => self.image_size(),
+            Some(ref frames) => frames.frame_rects[0].size(),
+        }
+    }
+
+    #[inline]
+    pub fn origin(&self) -> Vec2 {
+        Vec2::new(self.origin_x as f32, self.origin_y as f32)
+    }
+
+    #[inline]
+    pub fn rect(&self) -> Rect {
+        Rect {
+            min: Vec2::ZERO,
+            max: self.size(),
+        }
+    }
+
+    #[inline]
+    pub fn blend_mode(&self) -> BlendMode {
+        BlendMode::from_shader(self.shader)
+    }
+}
+
+/// How an element is composited over the sprites behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Alpha,
+    /// Light glows and other effects brightening what's behind them.
+    Additive,
+    /// Shadows darkening what's behind them.
+    Multiply,
+}
+
+impl BlendMode {
+    /// Blend modes of the known shader ids. The game files don't describe the shaders, so
+    /// these modes are assumptions kept in this one table, the viewer lists the ids missing
+    /// from it as unknown.
+    pub const SHADERS: [(u8, BlendMode); 3] = [
+        (0, BlendMode::Alpha),
+        (1, BlendMode::Additive),
+        (2, BlendMode::Multiply),
+    ];
+
+    /// Blend mode of a shader id, `None` when the id isn't in `SHADERS`.
+    pub fn of_shader(shader: u8) -> Option<Self> {
+        Self::SHADERS
+            .iter()
+            .find(|(id, _)| *id == shader)
+            .map(|(_, mode)| *mode)
+    }
+
+    /// Maps the shader id of an element, unknown ids are drawn with alpha blending.
+    #[inline]
+    pub fn from_shader(shader: u8) -> Self {
+        Self::of_shader(shader).unwrap_or(BlendMode::Alpha)
+    }
+}
+
+impl MapElement {
+    pub fn parse(bytes: &[u8], version: FormatVersion) -> ParseResult<Self> {
+        let offset = &mut 0;
+
+        let id: i32 = bytes.read(offset).field("id", *offset)?;
+        let result =
+            Self::parse_fields(bytes, offset, version, id).map_err(|err| err.in_element(id))?;
+        Ok((result, *offset))
+    }
+
+    fn parse_fields(
+        bytes: &[u8],
+        offset: &mut usize,
+        version: FormatVersion,
+        id: i32,
+    ) -> Result<Self, FormatError> {
+        let origin_x: i16 = bytes.read(offset).field("origin_x", *offset)?;
+        let origin_y: i16 = bytes.read(offset).field("origin_y", *offset)?;
+        let img_width: u16 = bytes.read(offset).field("img_width", *offset)?;
+        let img_height: u16 = bytes.read(offset).field("img_height", *offset)?;
+        let texture_id: i32 = bytes.read(offset).field("texture_id", *offset)?;
+        let flags: ElementFlags = bytes.read(offset).field("flags", *offset)?;
+        let visual_height: u8 = bytes.read(offset).field("visual_height", *offset)?;
+        let visibility_mask: u8 = bytes.read(offset).field("visibility_mask", *offset)?;
+        let export_mask: u8 = if version.has_export_mask() {
+            bytes.read(offset).field("export_mask", *offset)?
+        } else {
+            0
+        };
+        let shader: u8 = bytes.read(offset).field("shader", *offset)?;
+        let frame_count: u8 = bytes.read(offset).field("frame_count", *offset)?;
+        let animation = if frame_count > 0 {
+            Some(
+                bytes
+                    .read_with(offset, frame_count)
+                    .field("animation", *offset)?,
+            )
+        } else {
+            None
+        };
+        let ground_sound = bytes.read(offset).field("ground_sound", *offset)?;
+
+        let result = MapElement {
+            id,
+            origin_x,
+            origin_y,
+            img_width,
+            img_height,
+            texture_id,
+            flags,
+            visual_height,
+            visibility_mask,
+            export_mask,
+            shader,
+            animation,
+            ground_sound,
+        };
+        Ok(result)
+    }
+}
+
+impl<'a> TryRead<'a, FormatVersion> for MapElement {
+    fn try_read(bytes: &'a [u8], version: FormatVersion) -> byte::Result<(Self, usize)> {
+        Ok(MapElement::parse(bytes, version)?)
+    }
+}
+
+impl TryWrite<FormatVersion> for &MapElement {
+    fn try_write(self, bytes: &mut [u8], version: FormatVersion) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        bytes.write(offset, self.id)?;
+        bytes.write(offset, self.origin_x)?;
+        bytes.write(offset, self.origin_y)?;
+        bytes.write(offset, self.img_width)?;
+        bytes.write(offset, self.img_height)?;
+        bytes.write(offset, self.texture_id)?;
+        bytes.write(offset, &self.flags)?;
+        bytes.write(offset, self.visual_height)?;
+        bytes.write(offset, self.visibility_mask)?;
+        if version.has_export_mask() {
+            bytes.write(offset, self.export_mask)?;
+        }
+        bytes.write(offset, self.shader)?;
+        match &self.animation {
+            Some(frames) => {
+                bytes.write(offset, frames.frame_durations.len() as u8)?;
+                bytes.write(offset, frames)?;
+            }
+            None => bytes.write(offset, 0u8)?,
+        }
+        bytes.write(offset, self.ground_sound)?;
+        Ok(*offset)
+    }
+}
+
+#[bitfield]
+#[derive(Debug, Clone, Copy)]
+pub struct ElementFlags {
+    pub slope: B4,
+    pub is_flip: bool,
+    pub is_move_top: bool,
+    pub is_before_mobile: bool,
+    pub is_walkable: bool,
+}
+
+/// Flags are serialized by field rather than as their packed byte.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FlagFields {
+    slope: u8,
+    is_flip: bool,
+    is_move_top: bool,
+    is_before_mobile: bool,
+    is_walkable: bool,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ElementFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = FlagFields {
+            slope: self.slope(),
+            is_flip: self.is_flip(),
+            is_move_top: self.is_move_top(),
+            is_before_mobile: self.is_before_mobile(),
+            is_walkable: self.is_walkable(),
+        };
+        fields.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ElementFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let fields = FlagFields::deserialize(deserializer)?;
+        let flags = ElementFlags::new()
+            .with_slope_checked(fields.slope)
+            .map_err(|_| D::Error::custom("slope out of range"))?
+            .with_is_flip(fields.is_flip)
+            .with_is_move_top(fields.is_move_top)
+            .with_is_before_mobile(fields.is_before_mobile)
+            .with_is_walkable(fields.is_walkable);
+        Ok(flags)
+    }
+}
+
+impl Default for ElementFlags {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> TryRead<'a> for ElementFlags {
+    fn try_read(bytes: &'a [u8], _ctx: ()) -> byte::Result<(Self, usize)> {
+        let byte = bytes.read(&mut 0)?;
+        let result = ElementFlags::from_bytes([byte]);
+        Ok((result, 1))
+    }
+}
+
+impl TryWrite for &ElementFlags {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let [byte] = self.into_bytes();
+        bytes.write(&mut 0, byte)?;
+        Ok(1)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SerializedLibrary"))]
+pub struct ElementLibrary {
+    version: FormatVersion,
+    elements: Vec<MapElement>,
+    // rebuilt from the elements when deserialized
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    index: HashMap<i32, usize>,
+}
+
+impl ElementLibrary {
+    const ENTRY: &'static str = "elements.lib";
+
+    pub fn load<R: Seek + Read>(input: R) -> Result<Self, FormatError> {
+        let mut archive = zip::ZipArchive::new(input)?;
+        let mut entry = archive.by_name(Self::ENTRY)?;
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+
+        let (result, _) = FormatVersion::parse_any(&bytes, ElementLibrary::parse)
+            .map_err(|err| err.in_entry(Self::ENTRY))?;
+        Ok(result)
+    }
+
+    /// Writes the library to a new jar in the format version it was read with.
+    pub fn save<W: Write + Seek>(&self, output: W) -> Result<(), FormatError> {
+        let bytes = encode(self, self.version)
+            .map_err(|err| FormatError::Write(Self::ENTRY.into(), err))?;
+
+        let mut archive = zip::ZipWriter::new(output);
+        archive.start_file(Self::ENTRY, Default::default())?;
+        archive.write_all(&bytes)?;
+        archive.finish()?;
+        Ok(())
+    }
+
+    pub fn parse(bytes: &[u8], version: FormatVersion) -> ParseResult<Self> {
+        let offset = &mut 0;
+
+        let count: u32 = bytes.read(offset).field("count", *offset)?;
+        let mut elements = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (element, size) = MapElement::parse(&bytes[*offset..], version)
+                .map_err(|err| err.offset_by(*offset))?;
+            *offset += size;
+            elements.push(element);
+        }
+        Ok((Self::new(version, elements), *offset))
+    }
+
+    pub fn new(version: FormatVersion, elements: Vec<MapElement>) -> Self {
+        let index = elements
+            .iter()
+            .enumerate()
+            .map(|(idx, element)| (element.id, idx))
+            .collect();
+        Self {
+            version,
+            elements,
+            index,
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, id: i32) -> Option<&MapElement> {
+        self.index.get(&id).map(|idx| &self.elements[*idx])
+    }
+
+    /// Elements in the order they are stored in the library.
+    #[inline]
+    pub fn elements(&self) -> &[MapElement] {
+        &self.elements
+    }
+
+    #[inline]
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedLibrary {
+    version: FormatVersion,
+    elements: Vec<MapElement>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedLibrary> for ElementLibrary {
+    #[inline]
+    fn from(library: SerializedLibrary) -> Self {
+        Self::new(library.version, library.elements)
+    }
+}
+
+impl<'a> TryRead<'a, FormatVersion> for ElementLibrary {
+    fn try_read(bytes: &'a [u8], version: FormatVersion) -> byte::Result<(Self, usize)> {
+        Ok(ElementLibrary::parse(bytes, version)?)
+    }
+}
+
+impl TryWrite<FormatVersion> for &ElementLibrary {
+    fn try_write(self, bytes: &mut [u8], version: FormatVersion) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        bytes.write(offset, self.elements.len() as u32)?;
+        for element in &self.elements {
+            bytes.write_with(offset, element, version)?;
+        }
+        Ok(*offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// An element, with an animation of two frames when `animated`.
+    fn element_bytes(version: FormatVersion, id: i32, animated: bool) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(id.to_le_bytes());
+        bytes.extend((-3i16).to_le_bytes());
+        bytes.extend(40i16.to_le_bytes());
+        bytes.extend(64u16.to_le_bytes());
+        bytes.extend(32u16.to_le_bytes());
+        bytes.extend(501i32.to_le_bytes());
+        bytes.extend([0b1010_0011, 6, 1]);
+        if version.has_export_mask() {
+            bytes.push(2);
+        }
+        bytes.push(4);
+        if animated {
+            bytes.push(2);
+            bytes.extend(300u32.to_le_bytes());
+            for value in [16u16, 8, 32, 8, 100, 200] {
+                bytes.extend(value.to_le_bytes());
+            }
+            for coord in [0i16, 0, 16, 0] {
+                bytes.extend(coord.to_le_bytes());
+            }
+        } else {
+            bytes.push(0);
+        }
+        bytes.push(7);
+        bytes
+    }
+
+    #[test]
+    fn animated_element() {
+        let version = FormatVersion::Extended;
+        let bytes = element_bytes(version, 10, true);
+        let (element, size) = MapElement::parse(&bytes, version).unwrap();
+        assert_eq!(size, bytes.len());
+        assert_eq!(element.export_mask, 2);
+        let frames = element.animation.as_ref().unwrap();
+        assert_eq!(frames.frame_durations, [100, 200]);
+        assert_eq!(frames.frame_times, [0, 100]);
+        assert_eq!(encode(&element, version).unwrap(), bytes);
+    }
+
+    #[test]
+    fn static_element() {
+        let version = FormatVersion::Compact;
+        let bytes = element_bytes(version, 9, false);
+        let (element, size) = MapElement::parse(&bytes, version).unwrap();
+        assert_eq!(size, bytes.len());
+        assert!(element.animation.is_none());
+        assert_eq!((element.shader, element.ground_sound), (4, 7));
+        assert_eq!(encode(&element, version).unwrap(), bytes);
+    }
+
+    #[test]
+    fn library_roundtrip() {
+        for version in FormatVersion::ALL {
+            let mut bytes = 2u32.to_le_bytes().to_vec();
+            bytes.extend(element_bytes(version, 9, false));
+            bytes.extend(element_bytes(version, 10, true));
+            let (library, size) = ElementLibrary::parse(&bytes, version).unwrap();
+            assert_eq!(size, bytes.len());
+            assert_eq!(encode(&library, version).unwrap(), bytes);
+
+            let mut jar = Cursor::new(vec![]);
+            library.save(&mut jar).unwrap();
+            jar.set_position(0);
+            let library = ElementLibrary::load(jar).unwrap();
+            assert_eq!(library.version(), version);
+            assert_eq!(encode(&library, version).unwrap(), bytes);
+        }
+    }
+}
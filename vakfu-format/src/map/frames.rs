@@ -0,0 +1,108 @@
+use byte::ctx::Endian;
+use byte::{BytesExt, TryRead, TryWrite};
+use glam::Vec2;
+use itertools::Itertools;
+
+use crate::rect::Rect;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frames {
+    pub total_time: u32,
+    pub frame_times: Vec<u16>,
+    pub frame_durations: Vec<u16>,
+    pub frame_rects: Vec<Rect>,
+    pub total_width: u16,
+    pub total_height: u16,
+}
+
+impl Frames {
+    pub fn new(total_time: u32, frame_durations: &[u16], frame_coords: Vec<Rect>) -> Self {
+        let mut frame_times = Vec::with_capacity(frame_durations.len());
+        let mut frame_time = 0;
+        for dur in frame_durations {
+            frame_times.push(frame_time);
+            frame_time += dur;
+        }
+
+        Self {
+            total_time,
+            frame_times,
+            frame_durations: frame_durations.to_vec(),
+            frame_rects: frame_coords,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn frame_size(&self) -> Vec2 {
+        self.frame_rects.first().map(Rect::size).unwrap_or_default()
+    }
+
+    /// Index of the frame shown `ms` milliseconds into the animation.
+    #[inline]
+    pub fn frame_at(&self, ms: u64) -> usize {
+        frame_at(&self.frame_times, self.total_time, ms)
+    }
+}
+
+/// Index of the frame shown `ms` milliseconds in, `frame_times` being when each frame starts.
+pub fn frame_at(frame_times: &[u16], total_time: u32, ms: u64) -> usize {
+    let passed = ms.checked_rem(total_time as u64).unwrap_or(0);
+    frame_times
+        .binary_search(&(passed as u16))
+        .unwrap_or_else(|i| i.saturating_sub(1))
+}
+
+impl<'a> TryRead<'a, u8> for Frames {
+    fn try_read(bytes: &'a [u8], count: u8) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+
+        let total_time: u32 = bytes.read(offset)?;
+        let width: u16 = bytes.read(offset)?;
+        let height: u16 = bytes.read(offset)?;
+        let total_width: u16 = bytes.read(offset)?;
+        let total_height: u16 = bytes.read(offset)?;
+        let frame_durations: Vec<u16> = bytes
+            .read_iter(offset, Endian::default())
+            .take(count.into())
+            .collect();
+        let coords = bytes
+            .read_iter::<i16>(offset, Endian::default())
+            .take(count as usize * 2)
+            .tuples()
+            .map(|(x, y)| Rect {
+                min: Vec2::new(x as f32, y as f32),
+                max: Vec2::new(x as f32 + width as f32, y as f32 + height as f32),
+            })
+            .collect_vec();
+
+        let result = Frames {
+            total_width,
+            total_height,
+            ..Frames::new(total_time, &frame_durations, coords)
+        };
+        Ok((result, *offset))
+    }
+}
+
+impl TryWrite for &Frames {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        let size = self.frame_size();
+        bytes.write(offset, self.total_time)?;
+        bytes.write(offset, size.x as u16)?;
+        bytes.write(offset, size.y as u16)?;
+        bytes.write(offset, self.total_width)?;
+        bytes.write(offset, self.total_height)?;
+        for duration in &self.frame_durations {
+            bytes.write(offset, *duration)?;
+        }
+        for rect in &self.frame_rects {
+            bytes.write(offset, rect.min.x as i16)?;
+            bytes.write(offset, rect.min.y as i16)?;
+        }
+        Ok(*offset)
+    }
+}
@@ -0,0 +1,308 @@
+use std::io::{Read, Seek, Write};
+use std::ops::RangeInclusive;
+
+use glam::{IVec2, Vec2};
+use itertools::Itertools;
+
+use crate::encode::encode;
+use crate::error::{FormatError, Location};
+use crate::map::chunk::MapChunk;
+use crate::map::version::FormatVersion;
+
+pub mod chunk;
+#[allow(unused)]
+pub mod element;
+pub mod frames;
+pub mod path;
+pub mod source;
+pub mod sprite;
+pub mod version;
+pub mod walkability;
+
+pub const CELL_WIDTH: f32 = 86.;
+pub const CELL_HEIGHT: f32 = 43.;
+const ELEVATION_UNIT: f32 = 10.;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Map {
+    version: FormatVersion,
+    chunks: Vec<MapChunk>,
+    chunk_entries: Vec<String>,
+    // jar entries other than chunks, kept as-is so that saved maps stay complete
+    resources: Vec<(String, Vec<u8>)>,
+}
+
+impl Map {
+    /// Map made of the given chunks, each along with the name of its jar entry.
+    pub fn new(version: FormatVersion, chunks: Vec<(String, MapChunk)>) -> Self {
+        let (chunk_entries, chunks) = chunks.into_iter().unzip();
+        Self {
+            version,
+            chunks,
+            chunk_entries,
+            resources: vec![],
+        }
+    }
+
+    pub fn load<R: Read + Seek>(input: R) -> Result<Map, FormatError> {
+        let mut archive = zip::ZipArchive::new(input)?;
+        let mut chunks = Vec::with_capacity(archive.len());
+        let mut chunk_entries = Vec::with_capacity(archive.len());
+        let mut resources = vec![];
+        let mut version = None;
+        // chunks read before the layout was locked, they fit every layout
+        let mut ambiguous = vec![];
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if file.is_dir() {
+                continue;
+            }
+            let mut buffer = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut buffer)?;
+
+            if is_chunk_entry(file.name()) {
+                let is_locked = version.is_some();
+                let chunk =
+                    parse_chunk(&buffer, &mut version).map_err(|err| err.in_entry(file.name()))?;
+                if !is_locked && version.is_none() {
+                    ambiguous.push((chunks.len(), buffer));
+                }
+                chunks.push(chunk);
+                chunk_entries.push(file.name().to_owned());
+            } else {
+                resources.push((file.name().to_owned(), buffer));
+            }
+        }
+        // the layout they were read with may not be the one the rest of the map uses
+        if version.is_some() {
+            for (idx, bytes) in ambiguous {
+                chunks[idx] = parse_chunk(&bytes, &mut version)
+                    .map_err(|err| err.in_entry(&chunk_entries[idx]))?;
+            }
+        }
+        let version = version.unwrap_or_default();
+        let map = Map {
+            version,
+            chunks,
+            chunk_entries,
+            resources,
+        };
+        Ok(map)
+    }
+
+    /// Writes the map to a new jar in the format version it was read with.
+    pub fn save<W: Write + Seek>(&self, output: W) -> Result<(), FormatError> {
+        let mut archive = zip::ZipWriter::new(output);
+        for (name, chunk) in self.chunk_entries.iter().zip(&self.chunks) {
+            let bytes =
+                encode(chunk, self.version).map_err(|err| FormatError::Write(name.clone(), err))?;
+            archive.start_file(name, Default::default())?;
+            archive.write_all(&bytes)?;
+        }
+        for (name, bytes) in &self.resources {
+            archive.start_file(name, Default::default())?;
+            archive.write_all(bytes)?;
+        }
+        archive.finish()?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn chunks(&self) -> &[MapChunk] {
+        &self.chunks
+    }
+}
+
+/// Chunk entries are named after the chunk coordinates, as in `-1_2`.
+fn is_chunk_entry(name: &str) -> bool {
+    name.trim_matches(|c| char::is_numeric(c) || c == '-') == "_"
+}
+
+/// Parses a chunk with the given layout, or detects the layout when it's not known yet.
+/// All chunks of a map share a layout, it's only locked once a chunk fits a single one of them
+/// since chunks without sprites fit them all.
+fn parse_chunk(bytes: &[u8], version: &mut Option<FormatVersion>) -> Result<MapChunk, FormatError> {
+    match *version {
+        Some(version) => {
+            let (chunk, size) = MapChunk::parse(bytes, version)?;
+            if size != bytes.len() {
+                let loc = Location {
+                    chunk: Some((chunk.map_x, chunk.map_y)),
+                    field: "end",
+                    offset: size,
+                    ..Default::default()
+                };
+                return Err(FormatError::BadInput(loc, "Trailing bytes after the chunk"));
+            }
+            Ok(chunk)
+        }
+        None => {
+            let (chunk, detected) = FormatVersion::parse_unique(bytes, MapChunk::parse)?;
+            *version = detected;
+            Ok(chunk)
+        }
+    }
+}
+
+#[inline]
+pub fn iso_to_screen(vec: IVec2, height: i32) -> Vec2 {
+    let x = (vec.x - vec.y) as f32 * CELL_WIDTH / 2.;
+    let y = -(vec.x + vec.y) as f32 * CELL_HEIGHT / 2. + height as f32 * ELEVATION_UNIT;
+    Vec2::new(x, y)
+}
+
+/// Inverse of `iso_to_screen` at a given height, in fractional cell coordinates.
+#[inline]
+pub fn screen_to_iso_at(pos: Vec2, height: i32) -> Vec2 {
+    let diff = pos.x * 2. / CELL_WIDTH;
+    let sum = -(pos.y - height as f32 * ELEVATION_UNIT) * 2. / CELL_HEIGHT;
+    Vec2::new(sum + diff, sum - diff) / 2.
+}
+
+/// Cells that a screen position falls on when projected at each height of `heights`,
+/// from the highest to the lowest. Cells are centered on their `iso_to_screen` position,
+/// and since heights are only `ELEVATION_UNIT` pixels apart the same cell can come up
+/// at several heights.
+pub fn screen_to_iso(pos: Vec2, heights: RangeInclusive<i32>) -> Vec<(IVec2, i32)> {
+    heights
+        .rev()
+        .map(|height| (screen_to_iso_at(pos, height).round().as_ivec2(), height))
+        .collect()
+}
+
+/// Render order of every sprite of the map, listed in the order of `chunks`
+/// and the sprites within them.
+pub fn compute_z_orders(map: &Map) -> Vec<usize> {
+    let sprites = map.chunks().iter().flat_map(|chunk| &chunk.sprites);
+    let mut z_orders = vec![0; sprites.clone().count()];
+    let sorted = sprites
+        .enumerate()
+        .sorted_by_key(|(idx, sprite)| sprite.sort_key(*idx));
+    for (z_order, (idx, _)) in sorted.enumerate() {
+        z_orders[idx] = z_order;
+    }
+    z_orders
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::map::chunk::tests::chunk_bytes;
+    use crate::map::chunk::ChunkHeader;
+
+    fn jar(entries: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut jar = Cursor::new(vec![]);
+        let mut archive = zip::ZipWriter::new(&mut jar);
+        for (name, bytes) in entries {
+            archive.start_file(*name, Default::default()).unwrap();
+            archive.write_all(bytes).unwrap();
+        }
+        archive.finish().unwrap();
+        drop(archive);
+        jar.set_position(0);
+        jar
+    }
+
+    /// Saves the map and reads back the entry of each name.
+    fn saved_entries(map: &Map, names: &[&str]) -> Vec<Vec<u8>> {
+        let mut saved = Cursor::new(vec![]);
+        map.save(&mut saved).unwrap();
+        saved.set_position(0);
+        let mut archive = zip::ZipArchive::new(saved).unwrap();
+        names
+            .iter()
+            .map(|name| {
+                let mut bytes = vec![];
+                archive
+                    .by_name(name)
+                    .unwrap()
+                    .read_to_end(&mut bytes)
+                    .unwrap();
+                bytes
+            })
+            .collect()
+    }
+
+    #[test]
+    fn jar_roundtrip() {
+        let chunk = chunk_bytes(FormatVersion::Extended);
+        let map = Map::load(jar(&[("0_0", &chunk), ("env.dat", b"resource")])).unwrap();
+        let entries = saved_entries(&map, &["0_0", "env.dat"]);
+        assert_eq!(entries, [chunk, b"resource".to_vec()]);
+    }
+
+    #[test]
+    fn ambiguous_first_chunk() {
+        let extended = chunk_bytes(FormatVersion::Extended);
+        // a single sprite with an alternate color, cut off before its color table: its bytes
+        // read as a compact sprite taking the last byte of the element id as the color
+        let mut ambiguous = extended[..ChunkHeader::SIZE].to_vec();
+        ambiguous.extend(1u16.to_le_bytes());
+        ambiguous.extend([0, 1, 0, 1]);
+        ambiguous.push(1);
+        ambiguous.push(0x08);
+        ambiguous.extend(2i16.to_le_bytes());
+        ambiguous.extend([4, 1, 9]);
+        ambiguous.extend(77i32.to_le_bytes());
+        ambiguous.push(3);
+        ambiguous.extend(5i32.to_le_bytes());
+        ambiguous.push(1);
+        ambiguous.extend(1234i32.to_le_bytes());
+        for version in FormatVersion::ALL {
+            let (_, size) = MapChunk::parse(&ambiguous, version).unwrap();
+            assert_eq!(size, ambiguous.len());
+        }
+
+        let map = Map::load(jar(&[("0_0", &ambiguous), ("1_0", &extended)])).unwrap();
+        assert_eq!(map.version, FormatVersion::Extended);
+        let sprite = &map.chunks()[0].sprites[0];
+        assert_eq!(
+            (sprite.tag, sprite.group_key, sprite.element_id),
+            (9, 77, 1234)
+        );
+        assert_eq!(saved_entries(&map, &["0_0", "1_0"]), [ambiguous, extended]);
+    }
+
+    #[test]
+    fn screen_to_iso_roundtrip() {
+        for (x, y, height) in itertools::iproduct!(-20..20, -20..20, -30..30) {
+            let cell = IVec2::new(x, y);
+            let pos = iso_to_screen(cell, height);
+            let iso = screen_to_iso_at(pos, height);
+            assert!(
+                (iso - cell.as_vec2()).abs().max_element() < 1e-3,
+                "{} at {}",
+                cell,
+                height
+            );
+            assert!(
+                screen_to_iso(pos, -30..=30).contains(&(cell, height)),
+                "{} at {}",
+                cell,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn layout_detection() {
+        let extended = chunk_bytes(FormatVersion::Extended);
+        // a chunk without rects fits every layout
+        let (_, header_size) = ChunkHeader::parse(&extended).unwrap();
+        let empty = [&extended[..header_size], &[0, 0]].concat();
+
+        let mut version = None;
+        parse_chunk(&empty, &mut version).unwrap();
+        assert_eq!(version, None);
+        let chunk = parse_chunk(&extended, &mut version).unwrap();
+        assert_eq!(version, Some(FormatVersion::Extended));
+        assert_eq!(chunk.sprites.len(), 4);
+
+        let trailing = [&extended[..], &[0]].concat();
+        assert!(parse_chunk(&trailing, &mut version).is_err());
+    }
+}
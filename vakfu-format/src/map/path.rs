@@ -0,0 +1,191 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use glam::{const_ivec2, IVec2};
+
+use super::walkability::{CellState, Walkability};
+
+const NEIGHBOURS: [IVec2; 4] = [
+    const_ivec2!([1, 0]),
+    const_ivec2!([-1, 0]),
+    const_ivec2!([0, 1]),
+    const_ivec2!([0, -1]),
+];
+
+/// Finds the shortest path between two walkable cells with A*, moving to the four
+/// neighbouring cells. Moving between cells is allowed when their z differs by at most
+/// `max_step`, or one more when either cell is sloped. The path includes both ends.
+pub fn find_path(
+    walkability: &Walkability,
+    from: IVec2,
+    to: IVec2,
+    max_step: i16,
+) -> Option<Vec<IVec2>> {
+    if !walkability.is_walkable(from) || !walkability.is_walkable(to) {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::new();
+    let mut came_from = HashMap::new();
+    costs.insert(from, 0);
+    open.push(Reverse((distance(from, to), 0, from.x, from.y)));
+
+    while let Some(Reverse((_, cost, x, y))) = open.pop() {
+        let cell = IVec2::new(x, y);
+        if cell == to {
+            return Some(reconstruct(&came_from, to));
+        }
+        if cost > costs[&cell] {
+            continue;
+        }
+
+        let state = walkability.get(cell)?;
+        for next in NEIGHBOURS.iter().map(|dir| cell + *dir) {
+            match walkability.get(next) {
+                Some(next_state) if can_step(state, next_state, max_step) => {}
+                _ => continue,
+            }
+            let next_cost = cost + 1;
+            match costs.get(&next) {
+                Some(&known) if known <= next_cost => continue,
+                _ => {}
+            }
+            costs.insert(next, next_cost);
+            came_from.insert(next, cell);
+            let estimate = next_cost + distance(next, to);
+            open.push(Reverse((estimate, next_cost, next.x, next.y)));
+        }
+    }
+    None
+}
+
+#[inline]
+fn can_step(from: &CellState, to: &CellState, max_step: i16) -> bool {
+    let ramp = (from.slope != 0 || to.slope != 0) as i16;
+    to.walkable && (to.z - from.z).abs() <= max_step + ramp
+}
+
+#[inline]
+fn distance(a: IVec2, b: IVec2) -> u32 {
+    let diff = (a - b).abs();
+    (diff.x + diff.y) as u32
+}
+
+fn reconstruct(came_from: &HashMap<IVec2, IVec2>, to: IVec2) -> Vec<IVec2> {
+    let mut path = vec![to];
+    let mut cell = to;
+    while let Some(prev) = came_from.get(&cell) {
+        path.push(*prev);
+        cell = *prev;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::walkability::tests::{chunk, library, sprite, BLOCKED, FLAT, SLOPED};
+
+    /// Cells laid out from rows of `#` for blocked cells and digits for the z of walkable ones,
+    /// spaces are left out.
+    fn grid(rows: &[&str]) -> Walkability {
+        let mut sprites = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let cell = (x as i32, y as i32);
+                match c {
+                    '#' => sprites.push(sprite(cell, 0, BLOCKED)),
+                    '0'..='9' => sprites.push(sprite(cell, c as i16 - '0' as i16, FLAT)),
+                    _ => {}
+                }
+            }
+        }
+        Walkability::from_chunks([&chunk(sprites)], &library())
+    }
+
+    fn path(
+        walkability: &Walkability,
+        from: (i32, i32),
+        to: (i32, i32),
+        max_step: i16,
+    ) -> Option<Vec<(i32, i32)>> {
+        let path = find_path(walkability, from.into(), to.into(), max_step)?;
+        Some(path.into_iter().map(|cell| (cell.x, cell.y)).collect())
+    }
+
+    #[test]
+    fn straight() {
+        let walkability = grid(&["00000"]);
+        assert_eq!(
+            path(&walkability, (0, 0), (4, 0), 1),
+            Some(vec![(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)])
+        );
+    }
+
+    #[test]
+    fn detour() {
+        #[rustfmt::skip]
+        let walkability = grid(&[
+            "00#00",
+            "00#00",
+            "00000",
+        ]);
+        let found = path(&walkability, (0, 0), (4, 0), 1).unwrap();
+        assert_eq!(found.len(), 9);
+        assert_eq!(found.first(), Some(&(0, 0)));
+        assert_eq!(found.last(), Some(&(4, 0)));
+        assert!(found.contains(&(2, 2)));
+        for step in found.windows(2) {
+            let (a, b) = (IVec2::from(step[0]), IVec2::from(step[1]));
+            assert_eq!(distance(a, b), 1);
+            assert!(walkability.is_walkable(b));
+        }
+    }
+
+    #[test]
+    fn step_too_high() {
+        let walkability = grid(&["013"]);
+        assert!(path(&walkability, (0, 0), (1, 0), 1).is_some());
+        assert_eq!(path(&walkability, (0, 0), (2, 0), 1), None);
+        assert!(path(&walkability, (0, 0), (2, 0), 2).is_some());
+    }
+
+    #[test]
+    fn sloped_step() {
+        let chunk = chunk(vec![
+            sprite((0, 0), 0, FLAT),
+            sprite((1, 0), 2, SLOPED),
+            sprite((2, 0), 4, FLAT),
+            sprite((1, 1), 5, FLAT),
+        ]);
+        let walkability = Walkability::from_chunks([&chunk], &library());
+        // steps of one more than `max_step` onto and off the sloped cell, but not two more
+        assert_eq!(
+            path(&walkability, (0, 0), (2, 0), 1),
+            Some(vec![(0, 0), (1, 0), (2, 0)])
+        );
+        assert_eq!(path(&walkability, (1, 0), (1, 1), 1), None);
+        assert!(path(&walkability, (1, 0), (1, 1), 2).is_some());
+    }
+
+    #[test]
+    fn unreachable() {
+        #[rustfmt::skip]
+        let walkability = grid(&[
+            "0#0",
+            "##0",
+        ]);
+        assert_eq!(path(&walkability, (0, 0), (2, 1), 1), None);
+        // blocked and missing cells can't be ends of a path
+        assert_eq!(path(&walkability, (0, 0), (1, 0), 1), None);
+        assert_eq!(path(&walkability, (0, 0), (5, 5), 1), None);
+    }
+
+    #[test]
+    fn same_cell() {
+        let walkability = grid(&["00"]);
+        assert_eq!(path(&walkability, (1, 0), (1, 0), 1), Some(vec![(1, 0)]));
+    }
+}
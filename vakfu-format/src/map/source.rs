@@ -0,0 +1,137 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Mutex;
+
+use glam::IVec2;
+
+use super::chunk::{ChunkHeader, MapChunk};
+use super::version::FormatVersion;
+use super::{is_chunk_entry, parse_chunk};
+use crate::error::FormatError;
+use crate::jar::Jar;
+
+/// Map jar that only reads the chunk headers up front and decodes chunks on demand.
+pub struct MapSource {
+    jar: Jar,
+    entries: HashMap<(i32, i32), ChunkEntry>,
+    version: Mutex<Option<FormatVersion>>,
+    min_cell: IVec2,
+    max_cell: IVec2,
+    heights: RangeInclusive<i32>,
+}
+
+#[derive(Debug, Clone)]
+struct ChunkEntry {
+    name: String,
+    header: ChunkHeader,
+}
+
+impl MapSource {
+    const SPRITES_PER_CELL: u64 = 256;
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FormatError> {
+        let jar = Jar::open(path)?;
+        let mut entries = HashMap::new();
+        for name in jar.names() {
+            if !is_chunk_entry(&name) {
+                continue;
+            }
+            let bytes = jar.read_prefix(&name, ChunkHeader::SIZE)?;
+            let (header, _) = ChunkHeader::parse(&bytes).map_err(|err| err.in_entry(&name))?;
+            entries.insert((header.map_x, header.map_y), ChunkEntry { name, header });
+        }
+
+        let min_cell = entries
+            .values()
+            .map(|entry| IVec2::new(entry.header.min_x, entry.header.min_y))
+            .reduce(IVec2::min)
+            .unwrap_or_default();
+        let max_cell = entries
+            .values()
+            .map(|entry| IVec2::new(entry.header.max_x, entry.header.max_y))
+            .reduce(IVec2::max)
+            .unwrap_or_default();
+        let min_z = entries.values().map(|entry| entry.header.min_z).min();
+        let max_z = entries.values().map(|entry| entry.header.max_z).max();
+        let heights = min_z.unwrap_or(0) as i32..=max_z.unwrap_or(0) as i32;
+
+        let source = MapSource {
+            jar,
+            entries,
+            version: Mutex::new(None),
+            min_cell,
+            max_cell,
+            heights,
+        };
+        Ok(source)
+    }
+
+    /// Headers of all chunks of the map, in no particular order.
+    pub fn chunks(&self) -> impl Iterator<Item = &ChunkHeader> {
+        self.entries.values().map(|entry| &entry.header)
+    }
+
+    /// Range of cell heights over all chunks.
+    #[inline]
+    pub fn heights(&self) -> RangeInclusive<i32> {
+        self.heights.clone()
+    }
+
+    /// Reads and decodes the chunk at the given map position.
+    pub fn load(&self, map_x: i32, map_y: i32) -> Result<MapChunk, FormatError> {
+        let entry = self.entries.get(&(map_x, map_y)).ok_or_else(|| {
+            let name = format!("chunk ({}, {})", map_x, map_y);
+            io::Error::new(io::ErrorKind::NotFound, name)
+        })?;
+        let bytes = self.jar.read(&entry.name)?;
+
+        // don't hold the lock while parsing so that chunks can be decoded in parallel
+        let mut version = *self.version.lock().unwrap();
+        let chunk = parse_chunk(&bytes, &mut version).map_err(|err| err.in_entry(&entry.name))?;
+        if version.is_some() {
+            *self.version.lock().unwrap() = version;
+        }
+        Ok(chunk)
+    }
+
+    /// Ids of the elements placed on the map, decoding every chunk.
+    pub fn element_ids(&self) -> Result<HashSet<i32>, FormatError> {
+        let mut ids = HashSet::new();
+        for header in self.chunks() {
+            let chunk = self.load(header.map_x, header.map_y)?;
+            ids.extend(chunk.sprites.iter().map(|sprite| sprite.element_id));
+        }
+        Ok(ids)
+    }
+
+    /// Depth of each sprite of a chunk in `[0, 1)`, increasing with `MapSprite::sort_key`.
+    /// It only depends on the bounds of the map so that chunks can be placed independently.
+    pub fn depths(&self, chunk: &MapChunk) -> Vec<f32> {
+        chunk
+            .sprites
+            .iter()
+            .zip(chunk.cell_ranks())
+            .map(|(sprite, rank)| self.depth(IVec2::new(sprite.cell_x, sprite.cell_y), rank))
+            .collect()
+    }
+
+    /// Depth of the sprite drawn `rank`th on a cell, cells outside of the map are given the
+    /// depth of the closest cell within it.
+    pub fn depth(&self, cell: IVec2, rank: usize) -> f32 {
+        const MIN_BITS: u32 = 0x0D80_0000; // 2^-100
+        const MAX_BITS: u32 = 0x3F7F_FFFF; // just below 1
+
+        // the sprites of a cell are all stored in its chunk, numbering them within their cell
+        // keeps the depths unique across chunks
+        let size = (self.max_cell - self.min_cell + IVec2::ONE).max(IVec2::ONE);
+        let cell = (cell - self.min_cell).clamp(IVec2::ZERO, size - IVec2::ONE);
+        let cell_idx = cell.y as u64 * size.x as u64 + cell.x as u64;
+        let key = cell_idx * Self::SPRITES_PER_CELL + (rank as u64).min(Self::SPRITES_PER_CELL - 1);
+        // positive floats sort like their bits, stepping through them gives distinct
+        // depths to far more sprites than spreading the keys evenly over [0, 1) would
+        let bits = (MIN_BITS as u64 + key).min(MAX_BITS as u64) as u32;
+        f32::from_bits(bits)
+    }
+}
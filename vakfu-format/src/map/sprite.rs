@@ -0,0 +1,270 @@
+use byte::{BytesExt, TryRead, TryWrite};
+use glam::{const_vec2, IVec2, Vec2};
+
+use crate::color::Color;
+use crate::map::element::MapElement;
+use crate::map::iso_to_screen;
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapSprite {
+    pub cell_x: i32,
+    pub cell_y: i32,
+    pub cell_z: i16,
+    pub height: u8,
+    pub altitude_order: u8,
+    pub tag: u8,
+    pub element_id: i32,
+    pub group_key: i32,
+    pub group_id: i32,
+    pub layer: u8,
+    pub occluder: bool,
+    pub colors: SpriteColors,
+}
+
+impl MapSprite {
+    #[inline]
+    pub fn screen_position(&self) -> Vec2 {
+        let height = self.cell_z as i32 - self.height as i32;
+        iso_to_screen(IVec2::new(self.cell_x, self.cell_y), height)
+    }
+
+    /// Screen position of the top-left corner of the sprite drawn with `element`.
+    #[inline]
+    pub fn anchor_position(&self, element: &MapElement) -> Vec2 {
+        const FLIP_Y: Vec2 = const_vec2!([1., -1.]);
+        // origin needs to be flipped in the Y dimension for rendering
+        self.screen_position() - element.origin() * FLIP_Y
+    }
+
+    /// The color the sprite is tinted with, white unless it has a base tint. Gradients
+    /// aren't applied, so they don't tint the sprite either.
+    #[inline]
+    pub fn color(&self) -> Color {
+        self.colors
+            .tint
+            .as_ref()
+            .or(self.colors.alternate.as_ref())
+            .map(Tint::color)
+            .unwrap_or(Color::WHITE)
+    }
+
+    /// Key of the order sprites are drawn in, `hashcode` first. Sprites that share it are
+    /// told apart by their elevation, layer and then `order`, their position in the map file.
+    #[inline]
+    pub fn sort_key(&self, order: usize) -> (i64, i16, u8, u8, usize) {
+        (self.hashcode(), self.cell_z, self.height, self.layer, order)
+    }
+
+    #[inline]
+    pub fn hashcode(&self) -> i64 {
+        (self.altitude_order as i64 & 0x1FFFi64) << 6i64
+            | ((self.cell_x as i64 + 8192i64) & 0x3FFFi64) << 19i64
+            | ((self.cell_y as i64 + 8192i64) & 0x3FFFi64) << 34i64
+    }
+}
+
+/// Color table of a sprite, the flags byte in front of each sprite describes which entries
+/// are present:
+/// - `0x02` and `0x08`: rgb and alpha components of the base tint
+/// - `0x10`: an alternate tint with the same components as the base tint
+/// - `0x01` and `0x04`: rgb start and end colors of a gradient
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpriteColors {
+    pub flags: u8,
+    pub tint: Option<Tint>,
+    pub alternate: Option<Tint>,
+    pub gradient_start: Option<Tint>,
+    pub gradient_end: Option<Tint>,
+}
+
+impl SpriteColors {
+    #[inline]
+    pub fn empty(flags: u8) -> Self {
+        Self {
+            flags,
+            ..Default::default()
+        }
+    }
+
+    /// Present entries, in the order they are stored.
+    pub fn entries(&self) -> impl Iterator<Item = &Tint> {
+        [
+            &self.tint,
+            &self.alternate,
+            &self.gradient_start,
+            &self.gradient_end,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl<'a> TryRead<'a, u8> for SpriteColors {
+    fn try_read(bytes: &'a [u8], flags: u8) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+
+        let has_rgb = flags & 0x02 != 0;
+        let has_alpha = flags & 0x08 != 0;
+        let tint = Tint::read_opt(bytes, offset, has_rgb, has_alpha)?;
+        let alternate = if flags & 0x10 != 0 {
+            Tint::read_opt(bytes, offset, has_rgb, has_alpha)?
+        } else {
+            None
+        };
+        let gradient_start = Tint::read_opt(bytes, offset, flags & 0x01 != 0, false)?;
+        let gradient_end = Tint::read_opt(bytes, offset, flags & 0x04 != 0, false)?;
+
+        let colors = SpriteColors {
+            flags,
+            tint,
+            alternate,
+            gradient_start,
+            gradient_end,
+        };
+        Ok((colors, *offset))
+    }
+}
+
+impl TryWrite for &SpriteColors {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+        for tint in self.entries() {
+            bytes.write(offset, tint)?;
+        }
+        Ok(*offset)
+    }
+}
+
+/// Raw color components, each stored as a signed offset from the neutral value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tint {
+    pub rgb: Option<[i8; 3]>,
+    pub alpha: Option<i8>,
+}
+
+impl Tint {
+    pub fn color(&self) -> Color {
+        let [r, g, b] = self
+            .rgb
+            .map(|rgb| rgb.map(|v| teint(v) * 2.0))
+            .unwrap_or([1.0; 3]);
+        let a = self.alpha.map(teint).unwrap_or(1.0);
+        Color::rgba_linear(r, g, b, a)
+    }
+
+    fn read_opt(
+        bytes: &[u8],
+        offset: &mut usize,
+        has_rgb: bool,
+        has_alpha: bool,
+    ) -> byte::Result<Option<Self>> {
+        if !has_rgb && !has_alpha {
+            return Ok(None);
+        }
+        let rgb = if has_rgb {
+            Some([
+                bytes.read(offset)?,
+                bytes.read(offset)?,
+                bytes.read(offset)?,
+            ])
+        } else {
+            None
+        };
+        let alpha = if has_alpha {
+            Some(bytes.read(offset)?)
+        } else {
+            None
+        };
+        Ok(Some(Tint { rgb, alpha }))
+    }
+}
+
+impl TryWrite for &Tint {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+        for component in self.rgb.iter().flatten().chain(&self.alpha) {
+            bytes.write(offset, *component)?;
+        }
+        Ok(*offset)
+    }
+}
+
+#[inline]
+fn teint(v: i8) -> f32 {
+    (v as f32 / 255.0f32) + 0.5f32
+}
+
+#[cfg(test)]
+mod tests {
+    use byte::BytesExt;
+
+    use super::*;
+    use crate::encode::encode;
+
+    /// Size of the color table as it was computed before the entries were decoded.
+    fn size_from_tag(tag: u8) -> usize {
+        let mut size = 0;
+        if tag & 0x02 != 0 {
+            size += 3;
+        }
+        if tag & 0x08 != 0 {
+            size += 1;
+        }
+        if tag & 0x10 != 0 {
+            size *= 2;
+        }
+        if tag & 0x01 != 0 {
+            size += 3;
+        }
+        if tag & 0x04 != 0 {
+            size += 3;
+        }
+        size
+    }
+
+    #[test]
+    fn color_table_sizes() {
+        for tag in 0..32u8 {
+            // trailing bytes belong to the next sprite
+            let bytes: Vec<u8> = (1..=20).collect();
+            let offset = &mut 0;
+            let colors: SpriteColors = bytes.read_with(offset, tag).unwrap();
+            assert_eq!(*offset, size_from_tag(tag), "tag {:#04x}", tag);
+            assert_eq!(colors.flags, tag);
+        }
+    }
+
+    #[test]
+    fn color_table_roundtrip() {
+        for tag in 0..32u8 {
+            let bytes: Vec<u8> = (1..=size_from_tag(tag) as u8).collect();
+            let (colors, size) = SpriteColors::try_read(&bytes, tag).unwrap();
+            assert_eq!(size, bytes.len());
+            assert_eq!(encode(&colors, ()).unwrap(), bytes, "tag {:#04x}", tag);
+        }
+    }
+
+    #[test]
+    fn gradient_is_not_a_tint() {
+        for tag in [0x01, 0x04, 0x05] {
+            let bytes = [10u8; 6];
+            let (colors, _) = SpriteColors::try_read(&bytes, tag).unwrap();
+            let sprite = MapSprite {
+                colors,
+                ..Default::default()
+            };
+            assert_eq!(sprite.color(), Color::WHITE, "tag {:#04x}", tag);
+        }
+
+        // the base tint is used when there is one, whatever gradient comes with it
+        let (colors, _) = SpriteColors::try_read(&[0, 0, 0, 0, 10, 10, 10], 0x0B).unwrap();
+        let sprite = MapSprite {
+            colors,
+            ..Default::default()
+        };
+        assert_eq!(sprite.color(), Color::rgba_linear(1., 1., 1., 0.5));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::error::{FormatError, ParseResult};
+
+/// Binary layouts of the map and element library files shipped by different game releases.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FormatVersion {
+    /// Sprites without a tag byte and elements without an export mask.
+    #[default]
+    Compact,
+    /// Sprites with a tag byte after the altitude order and elements with an export mask
+    /// after the visibility mask.
+    Extended,
+}
+
+impl FormatVersion {
+    pub const ALL: [FormatVersion; 2] = [FormatVersion::Compact, FormatVersion::Extended];
+
+    /// Parses the buffer with the first known layout that consumes all of it.
+    /// When no layout fits, the error reported by the default layout is returned.
+    pub fn parse_any<T>(
+        bytes: &[u8],
+        parse: fn(&[u8], FormatVersion) -> ParseResult<T>,
+    ) -> Result<(T, FormatVersion), FormatError> {
+        let mut error = None;
+        for version in Self::ALL {
+            match parse(bytes, version) {
+                Ok((result, size)) if size == bytes.len() => return Ok((result, version)),
+                Ok(_) => {}
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        Err(error.unwrap_or(FormatError::UnknownVersion(None)))
+    }
+
+    /// Parses the buffer with every known layout and returns the result of the first one that
+    /// consumes all of it, along with that layout when no other one fits the buffer too.
+    /// When no layout fits, the error reported by the default layout is returned.
+    pub fn parse_unique<T>(
+        bytes: &[u8],
+        parse: fn(&[u8], FormatVersion) -> ParseResult<T>,
+    ) -> Result<(T, Option<FormatVersion>), FormatError> {
+        let mut found = None;
+        let mut fits = 0;
+        let mut error = None;
+        for version in Self::ALL {
+            match parse(bytes, version) {
+                Ok((result, size)) if size == bytes.len() => {
+                    fits += 1;
+                    found.get_or_insert((result, version));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        match found {
+            Some((result, version)) => Ok((result, (fits == 1).then_some(version))),
+            None => Err(error.unwrap_or(FormatError::UnknownVersion(None))),
+        }
+    }
+
+    #[inline]
+    pub fn has_sprite_tag(self) -> bool {
+        self == FormatVersion::Extended
+    }
+
+    #[inline]
+    pub fn has_export_mask(self) -> bool {
+        self == FormatVersion::Extended
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use glam::IVec2;
+
+use super::chunk::MapChunk;
+use super::element::ElementLibrary;
+
+/// Passability of a cell, taken from the elements drawn at its top z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellState {
+    pub z: i16,
+    pub walkable: bool,
+    /// One bit per raised corner of the cell, from the walkable element.
+    pub slope: u8,
+}
+
+/// Cell states of a map, built from its chunks.
+#[derive(Debug, Default)]
+pub struct Walkability {
+    cells: HashMap<IVec2, CellState>,
+}
+
+impl Walkability {
+    pub fn from_chunks<'a, I>(chunks: I, library: &ElementLibrary) -> Self
+    where
+        I: IntoIterator<Item = &'a MapChunk>,
+    {
+        let mut result = Self::default();
+        for chunk in chunks {
+            result.add_chunk(chunk, library);
+        }
+        result
+    }
+
+    /// Merges the cells of a chunk. A cell is walkable when any element at its top z is.
+    pub fn add_chunk(&mut self, chunk: &MapChunk, library: &ElementLibrary) {
+        for sprite in &chunk.sprites {
+            let element = match library.get(sprite.element_id) {
+                Some(element) => element,
+                None => continue,
+            };
+            let cell = IVec2::new(sprite.cell_x, sprite.cell_y);
+            let state = self.cells.entry(cell).or_insert(CellState {
+                z: sprite.cell_z,
+                walkable: false,
+                slope: 0,
+            });
+            if sprite.cell_z > state.z {
+                *state = CellState {
+                    z: sprite.cell_z,
+                    walkable: false,
+                    slope: 0,
+                };
+            }
+            if sprite.cell_z == state.z && !state.walkable && element.flags.is_walkable() {
+                state.walkable = true;
+                state.slope = element.flags.slope();
+            }
+        }
+    }
+
+    /// Adds the cells of another map part, replacing the cells both have.
+    pub fn merge(&mut self, other: Walkability) {
+        self.cells.extend(other.cells);
+    }
+
+    #[inline]
+    pub fn remove(&mut self, cell: IVec2) {
+        self.cells.remove(&cell);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    #[inline]
+    pub fn get(&self, cell: IVec2) -> Option<&CellState> {
+        self.cells.get(&cell)
+    }
+
+    #[inline]
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        matches!(self.get(cell), Some(state) if state.walkable)
+    }
+
+    #[inline]
+    pub fn cells(&self) -> impl Iterator<Item = (IVec2, &CellState)> {
+        self.cells.iter().map(|(cell, state)| (*cell, state))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::map::element::{ElementFlags, MapElement};
+    use crate::map::sprite::MapSprite;
+    use crate::map::version::FormatVersion;
+
+    pub(crate) const FLAT: i32 = 1;
+    pub(crate) const BLOCKED: i32 = 2;
+    pub(crate) const SLOPED: i32 = 3;
+
+    pub(crate) fn library() -> ElementLibrary {
+        let element = |id, flags| MapElement {
+            id,
+            origin_x: 0,
+            origin_y: 0,
+            img_width: 86,
+            img_height: 43,
+            texture_id: id,
+            flags,
+            visual_height: 0,
+            visibility_mask: 0,
+            export_mask: 0,
+            shader: 0,
+            animation: None,
+            ground_sound: 0,
+        };
+        let walkable = ElementFlags::new().with_is_walkable(true);
+        let elements = vec![
+            element(FLAT, walkable),
+            element(BLOCKED, ElementFlags::new()),
+            element(SLOPED, walkable.with_slope(0b0011)),
+        ];
+        ElementLibrary::new(FormatVersion::Compact, elements)
+    }
+
+    pub(crate) fn sprite(cell: (i32, i32), cell_z: i16, element_id: i32) -> MapSprite {
+        MapSprite {
+            cell_x: cell.0,
+            cell_y: cell.1,
+            cell_z,
+            element_id,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn chunk(sprites: Vec<MapSprite>) -> MapChunk {
+        MapChunk {
+            map_x: 0,
+            map_y: 0,
+            min_x: 0,
+            min_y: 0,
+            min_z: 0,
+            max_x: 0,
+            max_y: 0,
+            max_z: 0,
+            rects: vec![],
+            sprites,
+        }
+    }
+
+    #[test]
+    fn topmost_z() {
+        let chunk = chunk(vec![
+            // a walkable floor under a blocking element
+            sprite((0, 0), 0, FLAT),
+            sprite((0, 0), 3, BLOCKED),
+            // blocked at first, walkable once something at the top is
+            sprite((1, 0), 2, BLOCKED),
+            sprite((1, 0), 2, SLOPED),
+            sprite((1, 0), 2, FLAT),
+            // the top comes first in the chunk, lower elements don't count
+            sprite((2, 0), 4, FLAT),
+            sprite((2, 0), 1, BLOCKED),
+            // not in the library
+            sprite((3, 0), 0, 99),
+        ]);
+        let walkability = Walkability::from_chunks([&chunk], &library());
+
+        let state = |x| walkability.get(IVec2::new(x, 0)).copied();
+        let cell = |z, walkable, slope| Some(CellState { z, walkable, slope });
+        assert_eq!(state(0), cell(3, false, 0));
+        assert_eq!(state(1), cell(2, true, 0b0011));
+        assert_eq!(state(2), cell(4, true, 0));
+        assert_eq!(state(3), None);
+        assert_eq!(walkability.cells().count(), 3);
+    }
+
+    #[test]
+    fn remove_chunk() {
+        let library = library();
+        let first = chunk(vec![sprite((0, 0), 0, FLAT), sprite((1, 0), 0, FLAT)]);
+        let second = chunk(vec![sprite((5, 5), 2, FLAT), sprite((6, 5), 2, BLOCKED)]);
+
+        let mut walkability = Walkability::from_chunks([&first], &library);
+        let cells = Walkability::from_chunks([&second], &library);
+        let removed = cells.cells().map(|(cell, _)| cell).collect::<Vec<_>>();
+        walkability.merge(cells);
+        assert_eq!(walkability.cells().count(), 4);
+        assert_eq!(
+            walkability.get(IVec2::new(5, 5)).map(|state| state.z),
+            Some(2)
+        );
+
+        for cell in removed {
+            walkability.remove(cell);
+        }
+        assert_eq!(walkability.get(IVec2::new(5, 5)), None);
+        assert_eq!(walkability.get(IVec2::new(6, 5)), None);
+        assert!(walkability.is_walkable(IVec2::new(0, 0)));
+        assert!(walkability.is_walkable(IVec2::new(1, 0)));
+        assert_eq!(walkability.cells().count(), 2);
+    }
+}
@@ -0,0 +1,48 @@
+use glam::Vec2;
+
+/// An axis aligned rectangle, in pixels of an image.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Rect {
+    #[inline]
+    pub fn width(&self) -> f32 {
+        self.max.x - self.min.x
+    }
+
+    #[inline]
+    pub fn height(&self) -> f32 {
+        self.max.y - self.min.y
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl From<Rect> for bevy::sprite::Rect {
+    #[inline]
+    fn from(rect: Rect) -> Self {
+        bevy::sprite::Rect {
+            min: rect.min,
+            max: rect.max,
+        }
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl From<bevy::sprite::Rect> for Rect {
+    #[inline]
+    fn from(rect: bevy::sprite::Rect) -> Self {
+        Rect {
+            min: rect.min,
+            max: rect.max,
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::borrow::Cow;
+
+use byte::ctx::Bytes;
+use byte::{BytesExt, TryRead, TryWrite};
+
+use crate::error::{FieldContext, FormatError, Location, ParseResult};
+
+#[derive(Debug)]
+pub struct Tgam<'a> {
+    // 109 when a mask resize factor follows the header sizes
+    resize_mask: u8,
+    width: u16,
+    height: u16,
+    bytes: Cow<'a, [u8]>,
+    mask: AlphaMask<'a>,
+}
+
+impl<'a> Tgam<'a> {
+    #[inline]
+    pub fn bytes(&'a self) -> &'a [u8] {
+        &self.bytes
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        round_up_to_power_of_two(self.width.into())
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        round_up_to_power_of_two(self.height.into())
+    }
+
+    /// Width of the image, the texture is padded up to `width`.
+    #[inline]
+    pub fn image_width(&self) -> u32 {
+        self.width.into()
+    }
+
+    #[inline]
+    pub fn image_height(&self) -> u32 {
+        self.height.into()
+    }
+
+    #[inline]
+    pub fn mask(&self) -> &AlphaMask<'a> {
+        &self.mask
+    }
+
+    /// Returns the RGBA pixels of the texture with the alpha mask merged into the alpha channel.
+    pub fn rgba(&self) -> Vec<u8> {
+        let mut pixels = self.bytes.to_vec();
+        if self.mask.bytes.is_empty() {
+            return pixels;
+        }
+        let stride = self.width() as usize;
+        for y in 0..self.height as u32 {
+            for x in 0..self.width as u32 {
+                let alpha = (y as usize * stride + x as usize) * 4 + 3;
+                if let Some(a) = pixels.get_mut(alpha) {
+                    let mask = self.mask.alpha(x, y, self.width.into(), self.height.into());
+                    *a = ((*a as u16 * mask as u16) / 255) as u8;
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Returns the RGBA pixels of the image without the padding of the texture.
+    pub fn image_rgba(&self) -> Vec<u8> {
+        let stride = self.width() as usize * 4;
+        let row_len = self.image_width() as usize * 4;
+        self.rgba()
+            .chunks(stride)
+            .take(self.image_height() as usize)
+            .flat_map(|row| &row[..row_len.min(row.len())])
+            .copied()
+            .collect()
+    }
+}
+
+impl<'a> Tgam<'a> {
+    pub fn parse(bytes: &'a [u8]) -> ParseResult<Self> {
+        let offset = &mut 0;
+
+        let resize_mask: u8 = bytes.read(offset).field("resize_mask", *offset)?;
+        let header: &'a [u8] = bytes
+            .read_with(offset, Bytes::Len(3))
+            .field("header", *offset)?;
+        if header != b"AGT" {
+            let loc = Location {
+                field: "header",
+                offset: *offset - header.len(),
+                ..Default::default()
+            };
+            return Err(FormatError::BadInput(loc, "Invalid TGAM header"));
+        }
+
+        let width: u16 = bytes.read(offset).field("width", *offset)?;
+        let height: u16 = bytes.read(offset).field("height", *offset)?;
+        let tga_size: u32 = bytes.read(offset).field("tga_size", *offset)?;
+        let mask_size: u32 = bytes.read(offset).field("mask_size", *offset)?;
+        let mask_resize: u8 = if resize_mask == 109 {
+            bytes.read(offset).field("mask_resize", *offset)?
+        } else {
+            1
+        };
+        let tga_bytes: &[u8] = bytes
+            .read_with(offset, Bytes::Len(tga_size as usize))
+            .field("tga", *offset)?;
+        let mask_bytes: &[u8] = bytes
+            .read_with(offset, Bytes::Len(mask_size as usize))
+            .field("mask", *offset)?;
+
+        let mask = AlphaMask {
+            bytes: Cow::Borrowed(mask_bytes),
+            resize: mask_resize,
+        };
+
+        let tgam = Tgam {
+            resize_mask,
+            width,
+            height,
+            bytes: Cow::Borrowed(tga_bytes),
+            mask,
+        };
+        Ok((tgam, *offset))
+    }
+}
+
+impl<'a> TryRead<'a> for Tgam<'a> {
+    fn try_read(bytes: &'a [u8], _ctx: ()) -> byte::Result<(Self, usize)> {
+        Ok(Tgam::parse(bytes)?)
+    }
+}
+
+impl<'a> TryWrite for &'a Tgam<'a> {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        bytes.write(offset, self.resize_mask)?;
+        bytes.write_with(offset, &b"AGT"[..], ())?;
+        bytes.write(offset, self.width)?;
+        bytes.write(offset, self.height)?;
+        bytes.write(offset, self.bytes.len() as u32)?;
+        bytes.write(offset, self.mask.bytes.len() as u32)?;
+        if self.resize_mask == 109 {
+            bytes.write(offset, self.mask.resize)?;
+        }
+        bytes.write_with(offset, &*self.bytes, ())?;
+        bytes.write_with(offset, &*self.mask.bytes, ())?;
+        Ok(*offset)
+    }
+}
+
+#[derive(Debug)]
+pub struct AlphaMask<'a> {
+    bytes: Cow<'a, [u8]>,
+    resize: u8,
+}
+
+impl<'a> AlphaMask<'a> {
+    /// Returns the mask alpha for a pixel of a `width` by `height` image.
+    /// The mask is stored downscaled by the resize factor, either packed as one bit per pixel
+    /// or as one byte per pixel.
+    pub fn alpha(&self, x: u32, y: u32, width: u32, height: u32) -> u8 {
+        let resize = self.resize.max(1) as u32;
+        let mask_width = width.div_ceil(resize);
+        let mask_height = height.div_ceil(resize);
+        let idx = ((y / resize) * mask_width + x / resize) as usize;
+
+        if self.bytes.len() >= (mask_width * mask_height) as usize {
+            self.bytes.get(idx).copied().unwrap_or(u8::MAX)
+        } else {
+            match self.bytes.get(idx / 8) {
+                Some(byte) if byte & (1 << (idx % 8)) != 0 => u8::MAX,
+                Some(_) => 0,
+                None => u8::MAX,
+            }
+        }
+    }
+}
+
+fn round_up_to_power_of_two(value: u32) -> u32 {
+    if value < 2 {
+        return value;
+    }
+    let mut v = value - 1;
+    v = v | v >> 1;
+    v = v | v >> 2;
+    v = v | v >> 4;
+    v = v | v >> 8;
+    v = v | v >> 16;
+    v + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::encode;
+
+    /// A TGAM file, the mask resize factor is written when `resize_mask` is 109.
+    fn tgam_bytes(
+        resize_mask: u8,
+        size: (u16, u16),
+        pixels: &[u8],
+        resize: u8,
+        mask: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = vec![resize_mask];
+        bytes.extend(b"AGT");
+        bytes.extend(size.0.to_le_bytes());
+        bytes.extend(size.1.to_le_bytes());
+        bytes.extend((pixels.len() as u32).to_le_bytes());
+        bytes.extend((mask.len() as u32).to_le_bytes());
+        if resize_mask == 109 {
+            bytes.push(resize);
+        }
+        bytes.extend(pixels);
+        bytes.extend(mask);
+        bytes
+    }
+
+    /// Opaque gray pixels of a `width` by `height` texture.
+    fn opaque(width: usize, height: usize) -> Vec<u8> {
+        [0x80, 0x80, 0x80, 0xFF].repeat(width * height)
+    }
+
+    fn alphas(pixels: &[u8]) -> Vec<u8> {
+        pixels.chunks(4).map(|pixel| pixel[3]).collect()
+    }
+
+    #[test]
+    fn byte_mask() {
+        let bytes = tgam_bytes(0, (2, 2), &opaque(2, 2), 0, &[0, 255, 128, 255]);
+        let (tgam, size) = Tgam::parse(&bytes).unwrap();
+        assert_eq!(size, bytes.len());
+        assert_eq!(alphas(&tgam.rgba()), [0, 255, 128, 255]);
+    }
+
+    #[test]
+    fn bit_mask() {
+        let bytes = tgam_bytes(0, (4, 2), &opaque(4, 2), 0, &[0b1010_0101]);
+        let (tgam, _) = Tgam::parse(&bytes).unwrap();
+        assert_eq!(alphas(&tgam.rgba()), [255, 0, 255, 0, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn resized_mask() {
+        let bytes = tgam_bytes(109, (4, 4), &opaque(4, 4), 2, &[255, 0, 0, 255]);
+        let (tgam, size) = Tgam::parse(&bytes).unwrap();
+        assert_eq!(size, bytes.len());
+        assert_eq!(tgam.mask().resize, 2);
+        #[rustfmt::skip]
+        let expected = [
+            255, 255, 0, 0,
+            255, 255, 0, 0,
+            0, 0, 255, 255,
+            0, 0, 255, 255,
+        ];
+        assert_eq!(alphas(&tgam.rgba()), expected);
+    }
+
+    #[test]
+    fn resize_without_marker() {
+        // the factor is only read after the 109 marker
+        let bytes = tgam_bytes(0, (2, 2), &opaque(2, 2), 0, &[0, 255, 255, 0]);
+        let (tgam, size) = Tgam::parse(&bytes).unwrap();
+        assert_eq!(size, bytes.len());
+        assert_eq!(tgam.mask().resize, 1);
+        assert_eq!(alphas(&tgam.rgba()), [0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let pixels: Vec<u8> = (0..16).collect();
+        let bytes = tgam_bytes(0, (2, 2), &pixels, 0, &[]);
+        let (tgam, _) = Tgam::parse(&bytes).unwrap();
+        assert_eq!(tgam.rgba(), pixels);
+    }
+
+    #[test]
+    fn padded_image() {
+        // 3 by 1 images are stored in 4 by 1 textures
+        let bytes = tgam_bytes(0, (3, 1), &opaque(4, 1), 0, &[0, 255, 0]);
+        let (tgam, _) = Tgam::parse(&bytes).unwrap();
+        assert_eq!(tgam.width(), 4);
+        assert_eq!(alphas(&tgam.rgba()), [0, 255, 0, 255]);
+        assert_eq!(alphas(&tgam.image_rgba()), [0, 255, 0]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        for resize_mask in [0, 109] {
+            let bytes = tgam_bytes(resize_mask, (3, 2), &opaque(4, 2), 2, &[0xFF, 0x0F]);
+            let (tgam, size) = Tgam::parse(&bytes).unwrap();
+            assert_eq!(size, bytes.len());
+            assert_eq!(encode(&tgam, ()).unwrap(), bytes);
+        }
+    }
+}